@@ -1,32 +1,44 @@
-use glam::Vec3;
+use glam::{Quat, Vec2};
 
-use crate::{command::InputManager, camera::Camera, scene::{Node, NodeType, self}};
+use crate::{camera::CameraManager, command::InputManager, scene::{Node, NodeType}};
 
-pub fn standard_camera_update(node: &Node, input_manager: &InputManager, delta: f32) {
-    /*
-    if let NodeType::Camera(camera) = &node.node_type {
-        let mut direction = Vec3::ZERO;
-        let speed = 5.0;
-        if input_manager.is_key_pressed(winit::event::VirtualKeyCode::W) {
-            direction += camera.get_front();
-        }
-        if input_manager.is_key_pressed(winit::event::VirtualKeyCode::S) {
-            direction -= camera.get_front();
-        }
-        if input_manager.is_key_pressed(winit::event::VirtualKeyCode::A) {
-            direction -= camera.get_right();
-        }
-        if input_manager.is_key_pressed(winit::event::VirtualKeyCode::D) {
-            direction += camera.get_right();
-        }
-        if input_manager.is_key_pressed(winit::event::VirtualKeyCode::Space) {
-            direction += camera.get_up();
-        }
-        if input_manager.is_key_pressed(winit::event::VirtualKeyCode::LShift) {
-            direction -= camera.get_up();
-        }
-        camera.translate(direction * delta * speed);
+/// Radians turned per pixel of mouse movement
+const MOUSE_SENSITIVITY: f32 = 0.003;
+/// Keeps the camera from flipping over when looking straight up or down
+const MAX_PITCH: f32 = std::f32::consts::FRAC_PI_2 - 0.01;
+
+/// Fly camera for camera nodes, driven by the `move_forward`, `move_right`
+/// and `move_up` axes, and the `look_x` and `look_y` axes while `look` is
+/// held. By default that is WASD, Space and left shift, and dragging with
+/// the right mouse button. Moves at the node's `speed` in units per second
+/// and keeps the node's position in sync with the camera.
+pub fn standard_camera_update(node: &mut Node, input_manager: &InputManager, camera_manager: &mut CameraManager, delta: f32) {
+    let NodeType::Camera(camera_data) = &node.node_type else {
+        return;
+    };
+    let speed = camera_data.speed;
+    let Some(camera) = camera_manager.cameras.get_mut(&camera_data.camera) else {
+        return;
+    };
+
+    if input_manager.is_action_pressed("look") {
+        let mouse_delta = Vec2::new(input_manager.axis("look_x"), input_manager.axis("look_y")) * MOUSE_SENSITIVITY;
+        let front = camera.get_front();
+        let distance = (camera.target - camera.position).length();
+        let pitch = front.y.clamp(-1.0, 1.0).asin();
+        let new_pitch = (pitch - mouse_delta.y).clamp(-MAX_PITCH, MAX_PITCH);
+
+        let yawed = Quat::from_axis_angle(camera.up, -mouse_delta.x) * front;
+        let pitched = Quat::from_axis_angle(camera.get_right(), new_pitch - pitch) * yawed;
+        camera.target = camera.position + pitched.normalize_or_zero() * distance;
     }
-    */
-}
 
+    let direction = camera.get_front() * input_manager.axis("move_forward")
+        + camera.get_right() * input_manager.axis("move_right")
+        + camera.up * input_manager.axis("move_up");
+
+    let offset = direction.normalize_or_zero() * speed * delta;
+    camera.position += offset;
+    camera.target += offset;
+    node.transformation.position = camera.position;
+}
@@ -0,0 +1,138 @@
+use std::{collections::HashMap, fmt, path::{Path, PathBuf}};
+
+use serde::{Deserialize, Serialize};
+
+use crate::command::{Key, MouseButton};
+
+/// Something that can be held down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Binding {
+    Key(Key),
+    MouseButton(MouseButton),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum AxisSource {
+    /// 1 while `positive` is held, -1 while `negative` is, 0 for both
+    Buttons { positive: Binding, negative: Binding },
+    /// Raw mouse movement this frame, in pixels
+    MouseX,
+    MouseY,
+    /// Lines scrolled this frame
+    ScrollX,
+    ScrollY,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct AxisBinding {
+    pub source: AxisSource,
+    #[serde(default = "AxisBinding::default_scale")]
+    pub scale: f32,
+}
+
+impl AxisBinding {
+    fn default_scale() -> f32 {
+        1.0
+    }
+}
+
+/// Named actions and axes, so behaviors ask for "move_forward" instead of
+/// hard-coding keys. Usually read from a RON file like
+/// `config/bindings.ron`, which also provides the defaults.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ActionBindings {
+    /// An action is active while any of its bindings is held
+    #[serde(default)]
+    pub actions: HashMap<String, Vec<Binding>>,
+    /// An axis is the sum of its bindings
+    #[serde(default)]
+    pub axes: HashMap<String, Vec<AxisBinding>>,
+}
+
+impl ActionBindings {
+    /// The viewer's default controls.
+    pub fn standard() -> Self {
+        Self::from_ron(include_str!("../config/bindings.ron"))
+            .expect("config/bindings.ron is valid")
+    }
+
+    pub fn load<P: AsRef<Path>>(filename: P) -> Result<Self, BindingsError> {
+        let path = filename.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .map_err(|source| BindingsError::Io { path: path.to_path_buf(), source })?;
+        Self::from_ron(&contents)
+            .map_err(|source| BindingsError::Parse { path: path.to_path_buf(), source: Box::new(source) })
+    }
+
+    pub fn from_ron(contents: &str) -> Result<Self, ron::error::SpannedError> {
+        ron::from_str(contents)
+    }
+}
+
+#[derive(Debug)]
+pub enum BindingsError {
+    Io { path: PathBuf, source: std::io::Error },
+    Parse { path: PathBuf, source: Box<ron::error::SpannedError> },
+}
+
+impl fmt::Display for BindingsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BindingsError::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            BindingsError::Parse { path, source } => write!(f, "{}:{}", path.display(), source),
+        }
+    }
+}
+
+impl std::error::Error for BindingsError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BindingsError::Io { source, .. } => Some(source),
+            BindingsError::Parse { source, .. } => Some(source.as_ref()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec2;
+
+    use super::*;
+    use crate::command::InputManager;
+
+    #[test]
+    fn standard_bindings_parse() {
+        let bindings = ActionBindings::standard();
+        assert_eq!(bindings.actions["orbit"], [Binding::MouseButton(MouseButton::Left)]);
+        assert_eq!(bindings.axes["move_forward"], [AxisBinding {
+            source: AxisSource::Buttons { positive: Binding::Key(Key::W), negative: Binding::Key(Key::S) },
+            scale: 1.0,
+        }]);
+    }
+
+    #[test]
+    fn axis_sums_scaled_bindings() {
+        let bindings = ActionBindings::from_ron(r#"(
+            axes: {
+                "turn": [
+                    (source: Buttons(positive: Key(Right), negative: Key(Left)), scale: 2.0),
+                    (source: MouseX, scale: 0.5),
+                    (source: ScrollY),
+                ],
+            },
+        )"#).unwrap();
+        let mut input = InputManager::new();
+        input.set_bindings(bindings);
+
+        input.set_key_pressed(Key::Left, true);
+        input.add_mouse_motion(Vec2::new(6.0, 100.0));
+        input.add_scroll(Vec2::new(0.0, 1.5));
+        assert_eq!(input.axis("turn"), -2.0 + 3.0 + 1.5);
+        assert_eq!(input.axis("unknown"), 0.0);
+
+        // Both buttons cancel out, the mouse and scroll were this frame only
+        input.set_key_pressed(Key::Right, true);
+        input.end_frame();
+        assert_eq!(input.axis("turn"), 0.0);
+    }
+}
@@ -1,12 +1,15 @@
 use std::collections::HashMap;
 
-use glam::{Vec3, Mat4};
+use glam::{Vec2, Vec3, Mat4};
+
+use crate::command::InputManager;
 
 #[derive(Debug)]
 pub struct Camera {
     pub position: Vec3,
     pub target: Vec3,
     pub up: Vec3,
+    /// Vertical field of view in radians
     pub fov: f32,
     pub aspect_ratio: f32,
     pub z_near: f32,
@@ -23,6 +26,12 @@ pub struct CameraManager {
     pub active_camera: Option<CameraId>,
 }
 
+impl Default for CameraManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl CameraManager {
     pub fn new() -> Self {
         Self {
@@ -65,6 +74,7 @@ impl CameraManager {
 }
 
 impl Camera {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(position: Vec3, target: Vec3, up: Vec3, fov: f32, aspect_ratio: f32, z_near: f32, z_far: f32, speed: f32) -> Self {
         Self {
             position,
@@ -79,11 +89,7 @@ impl Camera {
     }
 
     pub fn get_view_matrix(&self) -> Mat4 {
-        let world_up = Vec3::new(0.0, 1.0, 0.0);
-        let camera_direction = Vec3::normalize(self.position - self.target);
-        let camera_right = Vec3::normalize(Vec3::cross(world_up, self.target));
-        let camera_up = Vec3::normalize(Vec3::cross(camera_direction, camera_right));
-        Mat4::look_at_rh(self.position, camera_direction, camera_up)
+        Mat4::look_at_rh(self.position, self.target, self.up)
     }
 
     pub fn get_projection_matrix(&self) -> Mat4 {
@@ -106,3 +112,86 @@ impl Camera {
         Vec3::normalize(Vec3::cross(self.get_right(), self.get_front()))
     }
 }
+
+/// Orbits a camera around its target: dragging while `orbit` is held
+/// rotates, the `zoom` axis zooms and dragging while `pan` is held pans.
+/// By default those are the left button, the scroll wheel and the middle
+/// button.
+///
+/// Everything is derived from the camera's position and target, so the
+/// camera can be moved by other means in between.
+#[derive(Debug)]
+pub struct OrbitController {
+    /// Radians per pixel dragged
+    pub rotate_speed: f32,
+    /// Fraction of the distance to the target covered per scroll line
+    pub zoom_speed: f32,
+    /// Distance to the target moved per pixel dragged, as a fraction of it
+    pub pan_speed: f32,
+    pub min_distance: f32,
+}
+
+impl Default for OrbitController {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OrbitController {
+    /// Keeps the camera from flipping over the poles
+    const MAX_PITCH: f32 = std::f32::consts::FRAC_PI_2 - 0.01;
+
+    pub fn new() -> Self {
+        Self {
+            rotate_speed: 0.01,
+            zoom_speed: 0.1,
+            pan_speed: 0.002,
+            min_distance: 0.1,
+        }
+    }
+
+    /// Applies this frame's mouse input to `camera`.
+    pub fn update(&self, input_manager: &InputManager, camera: &mut Camera) {
+        let cursor_delta = input_manager.cursor_delta();
+        if input_manager.is_action_pressed("orbit") {
+            self.rotate(camera, cursor_delta);
+        }
+        if input_manager.is_action_pressed("pan") {
+            self.pan(camera, cursor_delta);
+        }
+        self.zoom(camera, input_manager.axis("zoom"));
+    }
+
+    /// Positive `lines` zoom in.
+    pub fn zoom(&self, camera: &mut Camera, lines: f32) {
+        let offset = camera.position - camera.target;
+        let distance = (offset.length() * (1.0 - self.zoom_speed).powf(lines)).max(self.min_distance);
+        camera.position = camera.target + offset.normalize_or_zero() * distance;
+    }
+
+    /// Turns around the world Y axis for horizontal movement and tilts for
+    /// vertical movement.
+    pub fn rotate(&self, camera: &mut Camera, delta: Vec2) {
+        let offset = camera.position - camera.target;
+        let distance = offset.length();
+        if distance <= f32::EPSILON {
+            return;
+        }
+
+        let yaw = offset.x.atan2(offset.z) - delta.x * self.rotate_speed;
+        let pitch = ((offset.y / distance).asin() + delta.y * self.rotate_speed)
+            .clamp(-Self::MAX_PITCH, Self::MAX_PITCH);
+
+        let direction = Vec3::new(pitch.cos() * yaw.sin(), pitch.sin(), pitch.cos() * yaw.cos());
+        camera.position = camera.target + direction * distance;
+    }
+
+    /// Slides the camera and its target in the view plane, so the scene
+    /// follows the cursor.
+    pub fn pan(&self, camera: &mut Camera, delta: Vec2) {
+        let distance = (camera.position - camera.target).length();
+        let offset = (camera.get_up() * delta.y - camera.get_right() * delta.x) * self.pan_speed * distance;
+        camera.position += offset;
+        camera.target += offset;
+    }
+}
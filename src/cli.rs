@@ -0,0 +1,355 @@
+use std::{fmt, path::PathBuf, str::FromStr};
+
+use glam::{EulerRot, Quat, Vec3};
+use rs_sloth_renderer::{scene::Transform, shader::ShadingMode, turntable};
+
+const DEFAULT_MODEL: &str = "objs/african_head.obj";
+const DEFAULT_TEXTURE: &str = "objs/african_head_diffuse.tga";
+const DEFAULT_FRAME_DIR: &str = "turntable";
+const DEFAULT_REPLAY_DIR: &str = "replay";
+
+pub const USAGE: &str = "\
+Usage: rs-sloth-renderer [OPTIONS] [MODEL]
+
+Views an OBJ, glTF or GLB model, objs/african_head.obj by default.
+
+Options:
+  --scene FILE               Loads a RON scene file instead of a model. The
+                             camera options only apply if it has no active camera
+  --save-scene FILE          Writes the scene to FILE on exit
+  --texture FILE             Diffuse texture for OBJ submeshes without a material
+  --mesh X,Y,Z[:RX,RY,RZ[:S]]
+                             Places an instance of the model at X,Y,Z, rotated by
+                             RX,RY,RZ degrees and scaled by S. Can be repeated
+  --size WIDTHxHEIGHT        Resolution to render at [default: 800x600]
+  --camera-position X,Y,Z    [default: 0,0,8]
+  --camera-target X,Y,Z      [default: 0,0,-5]
+  --fov DEGREES              Vertical field of view, between 0 and 180
+                             [default: 45]
+  --shading flat|gouraud|phong
+                             [default: flat]
+  --output FILE              Renders a single frame to FILE and exits
+  --screenshot               Same as --output screenshot.png
+  --turntable FRAMES         Renders a full turn around the scene in FRAMES frames
+                             and exits
+  --turntable-node PATH      Spins the node at PATH, child indices from the root
+                             like 1/0, instead of orbiting the camera
+  --frame-dir DIR            Writes the turntable or replay as numbered PNGs into
+                             DIR [default: turntable or replay, none for a
+                             turntable with --gif]
+  --gif FILE                 Writes the turntable as an animated GIF
+  --gif-fps N                Playback rate of the GIF [default: 30]
+  --record FILE              Saves the session's input to FILE on exit
+  --replay FILE              Renders every frame of a recording into --frame-dir
+  --max-fps N                Caps the viewer's frame rate
+  -h, --help                 Prints this message
+";
+
+/// Everything the viewer can be told on the command line.
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub model: PathBuf,
+    /// Replaces the model, its meshes and the default light
+    pub scene: Option<PathBuf>,
+    pub save_scene: Option<PathBuf>,
+    /// Only used for OBJ models
+    pub texture: Option<PathBuf>,
+    /// One instance of the model per transform
+    pub meshes: Vec<Transform>,
+    pub width: usize,
+    pub height: usize,
+    pub camera_position: Vec3,
+    pub camera_target: Vec3,
+    /// Degrees
+    pub fov: f32,
+    pub shading: ShadingMode,
+    /// Render once into this file instead of opening a window
+    pub output: Option<PathBuf>,
+    /// Frames in a turntable, which replaces the viewer
+    pub turntable: Option<u32>,
+    /// Orbits the camera if `None`
+    pub turntable_node: Option<Vec<usize>>,
+    /// Where turntable and replay frames go
+    pub frame_dir: Option<PathBuf>,
+    pub gif: Option<PathBuf>,
+    pub gif_fps: u32,
+    pub record: Option<PathBuf>,
+    pub replay: Option<PathBuf>,
+    pub max_fps: Option<f32>,
+    pub help: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            model: PathBuf::from(DEFAULT_MODEL),
+            scene: None,
+            save_scene: None,
+            texture: None,
+            meshes: Vec::new(),
+            width: 800,
+            height: 600,
+            camera_position: Vec3::new(0.0, 0.0, 8.0),
+            camera_target: Vec3::new(0.0, 0.0, -5.0),
+            fov: 45.0,
+            shading: ShadingMode::Flat,
+            output: None,
+            turntable: None,
+            turntable_node: None,
+            frame_dir: None,
+            gif: None,
+            gif_fps: 30,
+            record: None,
+            replay: None,
+            max_fps: None,
+            help: false,
+        }
+    }
+}
+
+impl Options {
+    /// Parses the arguments following the program name.
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Self, CliError> {
+        let mut options = Self::default();
+        let mut model = None;
+        let mut texture = None;
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| CliError::MissingValue(arg.clone()));
+            match arg.as_str() {
+                "-h" | "--help" => options.help = true,
+                "--scene" => options.scene = Some(PathBuf::from(value()?)),
+                "--save-scene" => options.save_scene = Some(PathBuf::from(value()?)),
+                "--texture" => texture = Some(PathBuf::from(value()?)),
+                "--mesh" => options.meshes.push(parse_transform(&arg, &value()?)?),
+                "--size" => {
+                    let size = value()?;
+                    let (width, height) = size.split_once('x')
+                        .ok_or_else(|| CliError::InvalidValue { option: arg.clone(), value: size.clone() })?;
+                    options.width = parse_number(&arg, width)?;
+                    options.height = parse_number(&arg, height)?;
+                    if options.width == 0 || options.height == 0 {
+                        return Err(CliError::InvalidValue { option: arg, value: size });
+                    }
+                },
+                "--camera-position" => options.camera_position = parse_vec3(&arg, &value()?)?,
+                "--camera-target" => options.camera_target = parse_vec3(&arg, &value()?)?,
+                "--fov" => {
+                    let fov = value()?;
+                    let degrees: f32 = parse_number(&arg, &fov)?;
+                    if !degrees.is_finite() || degrees <= 0.0 || degrees >= 180.0 {
+                        return Err(CliError::InvalidValue { option: arg, value: fov });
+                    }
+                    options.fov = degrees;
+                },
+                "--shading" => {
+                    let shading = value()?;
+                    options.shading = match shading.as_str() {
+                        "flat" => ShadingMode::Flat,
+                        "gouraud" => ShadingMode::Gouraud,
+                        "phong" => ShadingMode::Phong,
+                        _ => return Err(CliError::InvalidValue { option: arg, value: shading }),
+                    };
+                },
+                "--output" => options.output = Some(PathBuf::from(value()?)),
+                "--screenshot" => options.output = Some(PathBuf::from("screenshot.png")),
+                "--turntable" => {
+                    let frames = value()?;
+                    match parse_number(&arg, &frames)? {
+                        0 => return Err(CliError::InvalidValue { option: arg, value: frames }),
+                        frames => options.turntable = Some(frames),
+                    }
+                },
+                "--turntable-node" => {
+                    let path = value()?;
+                    options.turntable_node = Some(turntable::parse_node_path(&path)
+                        .ok_or_else(|| CliError::InvalidValue { option: arg.clone(), value: path.clone() })?);
+                },
+                "--frame-dir" => options.frame_dir = Some(PathBuf::from(value()?)),
+                "--gif" => options.gif = Some(PathBuf::from(value()?)),
+                "--gif-fps" => options.gif_fps = parse_number(&arg, &value()?)?,
+                "--record" => options.record = Some(PathBuf::from(value()?)),
+                "--replay" => options.replay = Some(PathBuf::from(value()?)),
+                "--max-fps" => {
+                    let max_fps = value()?;
+                    let fps: f32 = parse_number(&arg, &max_fps)?;
+                    if !fps.is_finite() || fps <= 0.0 {
+                        return Err(CliError::InvalidValue { option: arg, value: max_fps });
+                    }
+                    options.max_fps = Some(fps);
+                },
+                _ if arg.starts_with('-') => return Err(CliError::UnknownOption(arg)),
+                _ if model.is_none() => model = Some(PathBuf::from(arg)),
+                _ => return Err(CliError::UnexpectedArgument(arg)),
+            }
+        }
+
+        if options.scene.is_some() && model.is_some() {
+            return Err(CliError::Conflict("MODEL", "--scene"));
+        }
+
+        if options.turntable.is_some() && options.frame_dir.is_none() && options.gif.is_none() {
+            options.frame_dir = Some(PathBuf::from(DEFAULT_FRAME_DIR));
+        }
+        // A turntable takes precedence over a replay
+        if options.turntable.is_none() && options.replay.is_some() && options.frame_dir.is_none() {
+            options.frame_dir = Some(PathBuf::from(DEFAULT_REPLAY_DIR));
+        }
+
+        // The default model comes with its texture and layout
+        match model {
+            Some(model) => {
+                options.model = model;
+                options.texture = texture;
+                if options.meshes.is_empty() {
+                    options.meshes.push(Transform::new(Vec3::ZERO, Quat::IDENTITY, Vec3::ONE));
+                }
+            },
+            None => {
+                options.texture = Some(texture.unwrap_or_else(|| PathBuf::from(DEFAULT_TEXTURE)));
+                if options.meshes.is_empty() {
+                    options.meshes = [Vec3::new(3.0, 0.0, -5.0), Vec3::new(-3.0, 0.0, -2.0), Vec3::new(-5.0, 2.0, -5.0)]
+                        .into_iter()
+                        .map(|position| Transform::new(position, Quat::IDENTITY, Vec3::ONE))
+                        .collect();
+                }
+            },
+        }
+
+        Ok(options)
+    }
+
+    /// Whether `model` should go through the glTF loader.
+    pub fn is_gltf(&self) -> bool {
+        self.model.extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("gltf") || extension.eq_ignore_ascii_case("glb"))
+    }
+}
+
+fn parse_number<T: FromStr>(option: &str, value: &str) -> Result<T, CliError> {
+    value.trim().parse()
+        .map_err(|_| CliError::InvalidValue { option: option.to_string(), value: value.to_string() })
+}
+
+fn parse_vec3(option: &str, value: &str) -> Result<Vec3, CliError> {
+    let components = value.split(',')
+        .map(|component| parse_number(option, component))
+        .collect::<Result<Vec<f32>, _>>()?;
+    match components[..] {
+        [x, y, z] => Ok(Vec3::new(x, y, z)),
+        _ => Err(CliError::InvalidValue { option: option.to_string(), value: value.to_string() }),
+    }
+}
+
+/// `X,Y,Z[:RX,RY,RZ[:S]]`, rotations in degrees applied X first.
+fn parse_transform(option: &str, value: &str) -> Result<Transform, CliError> {
+    let mut parts = value.split(':');
+    let position = parse_vec3(option, parts.next().unwrap_or(""))?;
+    let rotation = match parts.next() {
+        Some(rotation) => {
+            let degrees = parse_vec3(option, rotation)?;
+            Quat::from_euler(EulerRot::ZYX, degrees.z.to_radians(), degrees.y.to_radians(), degrees.x.to_radians())
+        },
+        None => Quat::IDENTITY,
+    };
+    let scale = match parts.next() {
+        Some(scale) => Vec3::splat(parse_number(option, scale)?),
+        None => Vec3::ONE,
+    };
+    if parts.next().is_some() {
+        return Err(CliError::InvalidValue { option: option.to_string(), value: value.to_string() });
+    }
+    Ok(Transform::new(position, rotation, scale))
+}
+
+#[derive(Debug)]
+pub enum CliError {
+    UnknownOption(String),
+    MissingValue(String),
+    InvalidValue { option: String, value: String },
+    UnexpectedArgument(String),
+    Conflict(&'static str, &'static str),
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::UnknownOption(option) => write!(f, "unknown option {}", option),
+            CliError::MissingValue(option) => write!(f, "{} needs a value", option),
+            CliError::InvalidValue { option, value } => write!(f, "invalid value for {}: {}", option, value),
+            CliError::UnexpectedArgument(arg) => write!(f, "unexpected argument {}, only one model can be viewed", arg),
+            CliError::Conflict(first, second) => write!(f, "{} and {} can't be used together", first, second),
+        }
+    }
+}
+
+impl std::error::Error for CliError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, CliError> {
+        Options::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    fn assert_invalid(args: &[&str], expected: &str) {
+        match parse(args) {
+            Err(CliError::InvalidValue { option, value }) => assert_eq!((option.as_str(), value.as_str()), (args[0], expected)),
+            other => panic!("{:?} parsed as {:?}", args, other),
+        }
+    }
+
+    #[test]
+    fn mesh_forms() {
+        let options = parse(&["model.obj", "--mesh", "1,2,3", "--mesh", "0,0,0:0,90,0", "--mesh", "0,0,0:0,0,0:2"]).unwrap();
+        assert_eq!(options.meshes.len(), 3);
+        assert_eq!(options.meshes[0].position, Vec3::new(1.0, 2.0, 3.0));
+        assert_eq!(options.meshes[0].scale, Vec3::ONE);
+        assert!(options.meshes[1].rotation.abs_diff_eq(Quat::from_rotation_y(90f32.to_radians()), 1e-6));
+        assert_eq!(options.meshes[2].rotation, Quat::IDENTITY);
+        assert_eq!(options.meshes[2].scale, Vec3::splat(2.0));
+
+        assert_invalid(&["--mesh", "0,0,0:0,0,0:1:1"], "0,0,0:0,0,0:1:1");
+        assert_invalid(&["--mesh", "0,0"], "0,0");
+    }
+
+    #[test]
+    fn size() {
+        let options = parse(&["--size", "320x200"]).unwrap();
+        assert_eq!((options.width, options.height), (320, 200));
+        assert_invalid(&["--size", "0x5"], "0x5");
+        assert_invalid(&["--size", "800"], "800");
+    }
+
+    #[test]
+    fn fov_must_be_an_angle_a_camera_can_have() {
+        assert_eq!(parse(&["--fov", "60"]).unwrap().fov, 60.0);
+        for fov in ["0", "-10", "180", "NaN", "inf"] {
+            assert_invalid(&["--fov", fov], fov);
+        }
+    }
+
+    #[test]
+    fn turntable_needs_frames() {
+        assert_eq!(parse(&["--turntable", "12"]).unwrap().turntable, Some(12));
+        assert_invalid(&["--turntable", "0"], "0");
+    }
+
+    #[test]
+    fn model_and_scene_conflict() {
+        assert!(matches!(parse(&["model.obj", "--scene", "scene.ron"]), Err(CliError::Conflict("MODEL", "--scene"))));
+    }
+
+    #[test]
+    fn unknown_option() {
+        assert!(matches!(parse(&["--frobnicate"]), Err(CliError::UnknownOption(option)) if option == "--frobnicate"));
+    }
+
+    #[test]
+    fn missing_value() {
+        assert!(matches!(parse(&["--size"]), Err(CliError::MissingValue(option)) if option == "--size"));
+    }
+}
@@ -0,0 +1,179 @@
+use glam::Vec4;
+
+use crate::shader::Varyings;
+
+////////////////////////////////////////////////////////////////
+// Sutherland-Hodgman polygon clipping in homogeneous clip space
+// https://en.wikipedia.org/wiki/Sutherland%E2%80%93Hodgman_algorithm
+//
+// Clipping happens before the perspective divide so that
+// vertices behind the camera (w <= 0) never get projected.
+////////////////////////////////////////////////////////////////
+
+/// A vertex in clip space along with the varyings that have to be
+/// interpolated whenever an edge is cut by a clip plane.
+#[derive(Debug, Clone, Copy)]
+pub struct ClipVertex<V> {
+    pub position: Vec4,
+    pub varyings: V,
+}
+
+impl<V: Varyings> ClipVertex<V> {
+    pub fn new(position: Vec4, varyings: V) -> Self {
+        Self { position, varyings }
+    }
+
+    fn lerp(&self, other: &ClipVertex<V>, t: f32) -> Self {
+        Self {
+            position: self.position.lerp(other.position, t),
+            varyings: self.varyings.lerp(other.varyings, t),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum ClipPlane {
+    Near,
+    Far,
+    Left,
+    Right,
+    Bottom,
+    Top,
+}
+
+impl ClipPlane {
+    const ALL: [ClipPlane; 6] = [
+        ClipPlane::Near,
+        ClipPlane::Far,
+        ClipPlane::Left,
+        ClipPlane::Right,
+        ClipPlane::Bottom,
+        ClipPlane::Top,
+    ];
+
+    /// Signed distance of `p` to the plane, positive on the visible side.
+    /// Depth follows glam's `perspective_rh` convention of 0 <= z <= w.
+    fn distance(&self, p: Vec4) -> f32 {
+        match self {
+            ClipPlane::Near => p.z,
+            ClipPlane::Far => p.w - p.z,
+            ClipPlane::Left => p.w + p.x,
+            ClipPlane::Right => p.w - p.x,
+            ClipPlane::Bottom => p.w + p.y,
+            ClipPlane::Top => p.w - p.y,
+        }
+    }
+}
+
+/// Clips a convex polygon against all six frustum planes.
+///
+/// Returns the vertices of the visible part in the same winding order,
+/// or an empty `Vec` if the polygon lies entirely outside the frustum.
+pub fn clip_polygon<V: Varyings>(vertices: &[ClipVertex<V>]) -> Vec<ClipVertex<V>> {
+    let mut output = vertices.to_vec();
+
+    for plane in ClipPlane::ALL {
+        if output.is_empty() {
+            break;
+        }
+
+        // Most triangles are fully inside, so skip the copy when we can
+        if output.iter().all(|v| plane.distance(v.position) >= 0.0) {
+            continue;
+        }
+
+        let input = std::mem::take(&mut output);
+        let mut previous = input[input.len() - 1];
+        let mut previous_distance = plane.distance(previous.position);
+
+        for current in input {
+            let current_distance = plane.distance(current.position);
+
+            // A vertex on the plane counts as inside and is kept as it is,
+            // cutting an edge into it would only repeat it
+            if (previous_distance > 0.0 && current_distance < 0.0) || (previous_distance < 0.0 && current_distance > 0.0) {
+                let t = previous_distance / (previous_distance - current_distance);
+                output.push(previous.lerp(&current, t));
+            }
+            if current_distance >= 0.0 {
+                output.push(current);
+            }
+
+            previous = current;
+            previous_distance = current_distance;
+        }
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec2;
+
+    use super::*;
+
+    /// A vertex at depth 0.5 whose varyings are its x and y, which stay
+    /// equal to its position wherever clipping cuts, as w is 1 throughout.
+    fn vertex(x: f32, y: f32, z: f32) -> ClipVertex<Vec2> {
+        ClipVertex::new(Vec4::new(x, y, z, 1.0), Vec2::new(x, y))
+    }
+
+    fn assert_polygon(actual: &[ClipVertex<Vec2>], expected: &[Vec4]) {
+        assert_eq!(actual.len(), expected.len(), "{:?}", actual);
+        for (vertex, expected) in actual.iter().zip(expected) {
+            assert!(vertex.position.abs_diff_eq(*expected, 1e-6), "{:?} != {:?}", vertex.position, expected);
+            assert!(vertex.varyings.abs_diff_eq(expected.truncate().truncate(), 1e-6), "{:?}", vertex);
+        }
+    }
+
+    #[test]
+    fn inside_is_unchanged() {
+        let triangle = [vertex(-0.5, -0.5, 0.5), vertex(0.5, -0.5, 0.5), vertex(0.0, 0.5, 0.5)];
+        let expected = triangle.map(|v| v.position);
+        assert_polygon(&clip_polygon(&triangle), &expected);
+    }
+
+    #[test]
+    fn outside_is_empty() {
+        let triangle = [vertex(1.5, 0.0, 0.5), vertex(3.0, 0.0, 0.5), vertex(2.0, 1.0, 0.5)];
+        assert!(clip_polygon(&triangle).is_empty());
+    }
+
+    #[test]
+    fn one_vertex_behind_the_near_plane() {
+        let triangle = [vertex(-0.5, -0.5, 0.5), vertex(0.5, -0.5, 0.5), vertex(0.0, 0.5, -0.5)];
+        let clipped = clip_polygon(&triangle);
+        // Both cut edges are halved, starting with the one into the first
+        // vertex
+        assert_polygon(&clipped, &[
+            Vec4::new(-0.25, 0.0, 0.0, 1.0),
+            Vec4::new(-0.5, -0.5, 0.5, 1.0),
+            Vec4::new(0.5, -0.5, 0.5, 1.0),
+            Vec4::new(0.25, 0.0, 0.0, 1.0),
+        ]);
+    }
+
+    #[test]
+    fn vertex_on_a_plane_is_not_repeated() {
+        let triangle = [vertex(-0.5, -0.5, 0.0), vertex(0.5, -0.5, 0.5), vertex(0.0, 0.5, -0.5)];
+        assert_polygon(&clip_polygon(&triangle), &[
+            Vec4::new(-0.5, -0.5, 0.0, 1.0),
+            Vec4::new(0.5, -0.5, 0.5, 1.0),
+            Vec4::new(0.25, 0.0, 0.0, 1.0),
+        ]);
+    }
+
+    #[test]
+    fn crossing_two_planes() {
+        // Sticks out past the right and the top plane, leaving the unit
+        // square in the upper right quarter of the view
+        let triangle = [vertex(0.0, 0.0, 0.5), vertex(3.0, 0.0, 0.5), vertex(0.0, 3.0, 0.5)];
+        assert_polygon(&clip_polygon(&triangle), &[
+            Vec4::new(0.0, 1.0, 0.5, 1.0),
+            Vec4::new(0.0, 0.0, 0.5, 1.0),
+            Vec4::new(1.0, 0.0, 0.5, 1.0),
+            Vec4::new(1.0, 1.0, 0.5, 1.0),
+        ]);
+    }
+}
@@ -1,24 +1,375 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
-use winit::event::VirtualKeyCode;
+use glam::Vec2;
+use serde::{Deserialize, Serialize};
 
+use crate::{bindings::{ActionBindings, AxisSource, Binding}, recording::{InputEvent, InputRecording, RecordedEvent}};
+
+macro_rules! keys {
+    ($($key:ident),* $(,)?) => {
+        /// Keyboard keys, named after winit's `VirtualKeyCode` so the
+        /// library doesn't have to depend on it.
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+        pub enum Key {
+            $($key),*
+        }
+
+        #[cfg(feature = "viewer")]
+        impl Key {
+            pub fn from_winit(key: winit::event::VirtualKeyCode) -> Option<Self> {
+                match key {
+                    $(winit::event::VirtualKeyCode::$key => Some(Key::$key),)*
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+keys! {
+    Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9, Key0,
+    A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+    Escape, F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12,
+    Left, Up, Right, Down,
+    Back, Return, Space, Tab,
+    LShift, RShift, LControl, RControl, LAlt, RAlt,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum MouseButton {
+    Left,
+    Right,
+    Middle,
+    Other(u16),
+}
+
+#[cfg(feature = "viewer")]
+impl MouseButton {
+    pub fn from_winit(button: winit::event::MouseButton) -> Self {
+        match button {
+            winit::event::MouseButton::Left => MouseButton::Left,
+            winit::event::MouseButton::Right => MouseButton::Right,
+            winit::event::MouseButton::Middle => MouseButton::Middle,
+            winit::event::MouseButton::Other(button) => MouseButton::Other(button),
+        }
+    }
+}
+
+/// Input state as of the current frame. Feed it window events as they
+/// arrive and call `end_frame` once every frame has been updated, which
+/// starts the next frame's deltas and edges from scratch.
 pub struct InputManager {
-    keys_pressed: HashMap<VirtualKeyCode, bool>
+    keys_pressed: HashMap<Key, bool>,
+    keys_just_pressed: HashSet<Key>,
+    keys_just_released: HashSet<Key>,
+    mouse_buttons_pressed: HashMap<MouseButton, bool>,
+    mouse_buttons_just_pressed: HashSet<MouseButton>,
+    mouse_buttons_just_released: HashSet<MouseButton>,
+    cursor_position: Option<Vec2>,
+    cursor_delta: Vec2,
+    mouse_delta: Vec2,
+    scroll_delta: Vec2,
+    bindings: ActionBindings,
+    /// Frames ended since recording started
+    frame: u64,
+    recording: Option<InputRecording>,
+}
+
+impl Default for InputManager {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl InputManager {
     pub fn new() -> Self {
         Self {
-            keys_pressed: HashMap::new()
+            keys_pressed: HashMap::new(),
+            keys_just_pressed: HashSet::new(),
+            keys_just_released: HashSet::new(),
+            mouse_buttons_pressed: HashMap::new(),
+            mouse_buttons_just_pressed: HashSet::new(),
+            mouse_buttons_just_released: HashSet::new(),
+            cursor_position: None,
+            cursor_delta: Vec2::ZERO,
+            mouse_delta: Vec2::ZERO,
+            scroll_delta: Vec2::ZERO,
+            bindings: ActionBindings::standard(),
+            frame: 0,
+            recording: None,
+        }
+    }
+
+    /// Applies an event and records it if a recording is running.
+    pub fn handle_event(&mut self, event: InputEvent) {
+        if let Some(recording) = &mut self.recording {
+            recording.events.push(RecordedEvent { frame: self.frame, event });
+        }
+
+        match event {
+            InputEvent::Key { key, pressed } => self.set_key_pressed(key, pressed),
+            InputEvent::MouseButton { button, pressed } => self.set_mouse_button_pressed(button, pressed),
+            InputEvent::CursorMoved(position) => self.set_cursor_position(position),
+            InputEvent::MouseMotion(delta) => self.add_mouse_motion(delta),
+            InputEvent::Scroll(delta) => self.add_scroll(delta),
         }
     }
 
-    pub fn set_key_pressed(&mut self, key: VirtualKeyCode, pressed: bool) {
-        self.keys_pressed.insert(key, pressed);
+    /// Starts capturing every event passed to `handle_event`, replacing
+    /// any recording in progress.
+    pub fn start_recording(&mut self) {
+        self.frame = 0;
+        self.recording = Some(InputRecording::default());
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    /// Ends the recording and returns what was captured.
+    pub fn stop_recording(&mut self) -> Option<InputRecording> {
+        self.recording.take()
+    }
+
+    /// Key repeats of a held key are not counted as new presses.
+    pub fn set_key_pressed(&mut self, key: Key, pressed: bool) {
+        let was_pressed = self.keys_pressed.insert(key, pressed).unwrap_or(false);
+        if pressed && !was_pressed {
+            self.keys_just_pressed.insert(key);
+        } else if !pressed && was_pressed {
+            self.keys_just_released.insert(key);
+        }
     }
 
-    pub fn is_key_pressed(&self, key: VirtualKeyCode) -> bool {
+    pub fn is_key_pressed(&self, key: Key) -> bool {
         *self.keys_pressed.get(&key).unwrap_or(&false)
     }
+
+    /// Whether the key went down during this frame.
+    pub fn is_key_just_pressed(&self, key: Key) -> bool {
+        self.keys_just_pressed.contains(&key)
+    }
+
+    /// Whether the key went up during this frame.
+    pub fn is_key_just_released(&self, key: Key) -> bool {
+        self.keys_just_released.contains(&key)
+    }
+
+    pub fn set_mouse_button_pressed(&mut self, button: MouseButton, pressed: bool) {
+        let was_pressed = self.mouse_buttons_pressed.insert(button, pressed).unwrap_or(false);
+        if pressed && !was_pressed {
+            self.mouse_buttons_just_pressed.insert(button);
+        } else if !pressed && was_pressed {
+            self.mouse_buttons_just_released.insert(button);
+        }
+    }
+
+    pub fn is_mouse_button_pressed(&self, button: MouseButton) -> bool {
+        *self.mouse_buttons_pressed.get(&button).unwrap_or(&false)
+    }
+
+    pub fn is_mouse_button_just_pressed(&self, button: MouseButton) -> bool {
+        self.mouse_buttons_just_pressed.contains(&button)
+    }
+
+    pub fn is_mouse_button_just_released(&self, button: MouseButton) -> bool {
+        self.mouse_buttons_just_released.contains(&button)
+    }
+
+    /// In window pixels, y pointing down. The first position only sets
+    /// where the cursor is, later ones add to `cursor_delta`.
+    pub fn set_cursor_position(&mut self, position: Vec2) {
+        if let Some(previous) = self.cursor_position {
+            self.cursor_delta += position - previous;
+        }
+        self.cursor_position = Some(position);
+    }
+
+    /// `None` until the cursor has entered the window.
+    pub fn cursor_position(&self) -> Option<Vec2> {
+        self.cursor_position
+    }
+
+    /// How far the cursor moved over the window this frame.
+    pub fn cursor_delta(&self) -> Vec2 {
+        self.cursor_delta
+    }
+
+    /// Accumulates raw mouse movement, in pixels with y pointing down.
+    /// Unlike the cursor it keeps going at the edges of the window.
+    pub fn add_mouse_motion(&mut self, delta: Vec2) {
+        self.mouse_delta += delta;
+    }
+
+    /// Raw mouse movement this frame.
+    pub fn mouse_delta(&self) -> Vec2 {
+        self.mouse_delta
+    }
+
+    /// Accumulates scrolling in lines, positive y scrolling up (away from
+    /// the user).
+    pub fn add_scroll(&mut self, delta: Vec2) {
+        self.scroll_delta += delta;
+    }
+
+    /// Lines scrolled this frame.
+    pub fn scroll_delta(&self) -> Vec2 {
+        self.scroll_delta
+    }
+
+    pub fn set_bindings(&mut self, bindings: ActionBindings) {
+        self.bindings = bindings;
+    }
+
+    pub fn bindings(&self) -> &ActionBindings {
+        &self.bindings
+    }
+
+    fn is_binding_pressed(&self, binding: Binding) -> bool {
+        match binding {
+            Binding::Key(key) => self.is_key_pressed(key),
+            Binding::MouseButton(button) => self.is_mouse_button_pressed(button),
+        }
+    }
+
+    fn is_binding_just_pressed(&self, binding: Binding) -> bool {
+        match binding {
+            Binding::Key(key) => self.is_key_just_pressed(key),
+            Binding::MouseButton(button) => self.is_mouse_button_just_pressed(button),
+        }
+    }
+
+    fn is_binding_just_released(&self, binding: Binding) -> bool {
+        match binding {
+            Binding::Key(key) => self.is_key_just_released(key),
+            Binding::MouseButton(button) => self.is_mouse_button_just_released(button),
+        }
+    }
+
+    fn action_bindings(&self, action: &str) -> impl Iterator<Item = Binding> + '_ {
+        self.bindings.actions.get(action).into_iter().flatten().copied()
+    }
+
+    /// Whether any binding of the action is held. Unknown actions are
+    /// never pressed.
+    pub fn is_action_pressed(&self, action: &str) -> bool {
+        self.action_bindings(action).any(|binding| self.is_binding_pressed(binding))
+    }
+
+    pub fn is_action_just_pressed(&self, action: &str) -> bool {
+        self.action_bindings(action).any(|binding| self.is_binding_just_pressed(binding))
+    }
+
+    pub fn is_action_just_released(&self, action: &str) -> bool {
+        self.action_bindings(action).any(|binding| self.is_binding_just_released(binding))
+    }
+
+    /// Sum of the axis' bindings this frame, 0 for unknown axes.
+    pub fn axis(&self, axis: &str) -> f32 {
+        self.bindings.axes.get(axis).into_iter().flatten()
+            .map(|binding| {
+                let value = match binding.source {
+                    AxisSource::Buttons { positive, negative } => {
+                        self.is_binding_pressed(positive) as i32 as f32 - self.is_binding_pressed(negative) as i32 as f32
+                    },
+                    AxisSource::MouseX => self.mouse_delta.x,
+                    AxisSource::MouseY => self.mouse_delta.y,
+                    AxisSource::ScrollX => self.scroll_delta.x,
+                    AxisSource::ScrollY => self.scroll_delta.y,
+                };
+                value * binding.scale
+            })
+            .sum()
+    }
+
+    /// Advances to the next frame: clears the just pressed and released
+    /// sets and the per-frame deltas. Held keys and buttons stay held.
+    pub fn end_frame(&mut self) {
+        self.keys_just_pressed.clear();
+        self.keys_just_released.clear();
+        self.mouse_buttons_just_pressed.clear();
+        self.mouse_buttons_just_released.clear();
+        self.cursor_delta = Vec2::ZERO;
+        self.mouse_delta = Vec2::ZERO;
+        self.scroll_delta = Vec2::ZERO;
+
+        self.frame += 1;
+        if let Some(recording) = &mut self.recording {
+            recording.frame_count = self.frame;
+        }
+    }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_edges_last_one_frame() {
+        let mut input = InputManager::new();
+        input.set_key_pressed(Key::W, true);
+        assert!(input.is_key_pressed(Key::W));
+        assert!(input.is_key_just_pressed(Key::W));
+        assert!(!input.is_key_just_released(Key::W));
+
+        input.end_frame();
+        assert!(input.is_key_pressed(Key::W));
+        assert!(!input.is_key_just_pressed(Key::W));
+
+        input.set_key_pressed(Key::W, false);
+        assert!(!input.is_key_pressed(Key::W));
+        assert!(input.is_key_just_released(Key::W));
+
+        input.end_frame();
+        assert!(!input.is_key_just_released(Key::W));
+    }
+
+    #[test]
+    fn key_repeat_is_not_a_new_press() {
+        let mut input = InputManager::new();
+        input.set_key_pressed(Key::Space, true);
+        input.end_frame();
+        input.set_key_pressed(Key::Space, true);
+        assert!(input.is_key_pressed(Key::Space));
+        assert!(!input.is_key_just_pressed(Key::Space));
+    }
+
+    #[test]
+    fn releasing_an_unpressed_key_is_not_an_edge() {
+        let mut input = InputManager::new();
+        input.set_key_pressed(Key::A, false);
+        assert!(!input.is_key_just_released(Key::A));
+    }
+
+    #[test]
+    fn mouse_button_edges() {
+        let mut input = InputManager::new();
+        input.set_mouse_button_pressed(MouseButton::Left, true);
+        input.set_mouse_button_pressed(MouseButton::Left, true);
+        assert!(input.is_mouse_button_just_pressed(MouseButton::Left));
+        input.end_frame();
+        assert!(!input.is_mouse_button_just_pressed(MouseButton::Left));
+        input.set_mouse_button_pressed(MouseButton::Left, false);
+        assert!(input.is_mouse_button_just_released(MouseButton::Left));
+        assert!(!input.is_mouse_button_pressed(MouseButton::Left));
+    }
+
+    #[test]
+    fn end_frame_clears_the_deltas() {
+        let mut input = InputManager::new();
+        input.set_cursor_position(Vec2::new(10.0, 10.0));
+        input.set_cursor_position(Vec2::new(13.0, 6.0));
+        input.add_mouse_motion(Vec2::new(2.0, -1.0));
+        input.add_mouse_motion(Vec2::new(1.0, 1.0));
+        input.add_scroll(Vec2::new(0.0, 2.0));
+        assert_eq!(input.cursor_delta(), Vec2::new(3.0, -4.0));
+        assert_eq!(input.mouse_delta(), Vec2::new(3.0, 0.0));
+        assert_eq!(input.scroll_delta(), Vec2::new(0.0, 2.0));
+
+        input.end_frame();
+        assert_eq!(input.cursor_delta(), Vec2::ZERO);
+        assert_eq!(input.mouse_delta(), Vec2::ZERO);
+        assert_eq!(input.scroll_delta(), Vec2::ZERO);
+        assert_eq!(input.cursor_position(), Some(Vec2::new(13.0, 6.0)));
+    }
+}
@@ -0,0 +1,305 @@
+use std::{fmt, path::{Path, PathBuf}, sync::Arc};
+
+use glam::{Quat, Vec3};
+use log::{info, warn};
+
+use crate::{
+    material::{Material, MaterialId, MaterialManager},
+    model::{FaceVertex, Model, ModelId, ModelManager, ModelSource, Submesh},
+    scene::{ModelData, Node, NodeType, Transform},
+    shader::ShadingMode,
+    texture::{Filter, MipFilter, Sampler, Texture, TextureId, TextureManager, Wrap},
+};
+
+/// The contents of a glTF 2.0 file (`.gltf` with its buffers, or a
+/// self-contained `.glb`) after registering its meshes, materials and
+/// images with the managers.
+///
+/// Cameras, lights, skins and animations are not imported.
+#[derive(Debug)]
+pub struct GltfScene {
+    /// One model per glTF mesh, with a submesh per primitive
+    pub models: Vec<ModelId>,
+    pub materials: Vec<MaterialId>,
+    /// One texture per glTF image
+    pub textures: Vec<TextureId>,
+    pub nodes: Vec<GltfNode>,
+    /// Indices into `nodes` of the default scene's root nodes
+    pub roots: Vec<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct GltfNode {
+    pub name: Option<String>,
+    pub position: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+    /// Index into `GltfScene::models`
+    pub mesh: Option<usize>,
+    pub children: Vec<usize>,
+}
+
+/// Whether `path` names a glTF file, going by its extension.
+pub fn is_gltf_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("gltf") || extension.eq_ignore_ascii_case("glb"))
+}
+
+impl GltfScene {
+    pub fn load<P: AsRef<Path>>(filename: P,
+                                model_manager: &mut ModelManager,
+                                texture_manager: &mut TextureManager,
+                                material_manager: &mut MaterialManager) -> Result<Self, GltfError> {
+        let path = filename.as_ref();
+        let (document, buffers, images) = gltf::import(path)
+            .map_err(|source| GltfError { path: path.to_path_buf(), source })?;
+
+        let textures: Vec<TextureId> = images.iter()
+            .map(|image| texture_manager.add_texture(texture_from_image(image)))
+            .collect();
+        let texture_of = |texture: gltf::Texture| textures[texture.source().index()];
+
+        let materials: Vec<MaterialId> = document.materials()
+            .map(|material| {
+                let pbr = material.pbr_metallic_roughness();
+                let [r, g, b, a] = pbr.base_color_factor();
+                // No specular workflow in core glTF, so rough surfaces
+                // simply get a dimmer and wider highlight
+                let roughness = pbr.roughness_factor().clamp(0.05, 1.0);
+                let mut result = Material::new(material.name().unwrap_or(""));
+                result.diffuse = Vec3::new(r, g, b);
+                result.opacity = a;
+                result.specular = Vec3::splat(0.5 * (1.0 - roughness));
+                result.shininess = 2.0 / roughness.powi(4) - 2.0;
+                if let Some(info) = pbr.base_color_texture() {
+                    result.diffuse_map = Some(texture_of(info.texture()));
+                    result.sampler = sampler_from_gltf(&info.texture().sampler());
+                }
+                result.bump_map = material.normal_texture().map(|normal| texture_of(normal.texture()));
+                material_manager.add_material(result)
+            })
+            .collect();
+
+        let models: Vec<ModelId> = document.meshes()
+            .map(|mesh| {
+                let source = ModelSource { path: path.to_path_buf(), mesh: Some(mesh.index()) };
+                model_manager.add_model_from(model_from_mesh(&mesh, &buffers, &materials), source)
+            })
+            .collect();
+
+        let nodes: Vec<GltfNode> = document.nodes()
+            .map(|node| {
+                let (position, rotation, scale) = node.transform().decomposed();
+                GltfNode {
+                    name: node.name().map(str::to_string),
+                    position: Vec3::from_array(position),
+                    rotation: Quat::from_array(rotation),
+                    scale: Vec3::from_array(scale),
+                    mesh: node.mesh().map(|mesh| mesh.index()),
+                    children: node.children().map(|child| child.index()).collect(),
+                }
+            })
+            .collect();
+
+        let roots = document.default_scene()
+            .or_else(|| document.scenes().next())
+            .map_or_else(Vec::new, |scene| scene.nodes().map(|node| node.index()).collect());
+
+        info!("glTF loaded: {} meshes, {} materials, {} textures, {} nodes",
+              models.len(), materials.len(), textures.len(), nodes.len());
+        Ok(Self { models, materials, textures, nodes, roots })
+    }
+
+    /// Builds the node hierarchy of the scene under a new group node.
+    pub fn instantiate(&self, shading: ShadingMode) -> Node {
+        let mut root = Node::new(
+            Box::new(|_node, _input_manager, _camera_manager, _delta_time| {}),
+            Transform::new(Vec3::ZERO, Quat::IDENTITY, Vec3::ONE),
+            NodeType::Group);
+        for &index in &self.roots {
+            root.add_child(self.instantiate_node(index, shading));
+        }
+        root
+    }
+
+    fn instantiate_node(&self, index: usize, shading: ShadingMode) -> Node {
+        let gltf_node = &self.nodes[index];
+        let node_type = match gltf_node.mesh {
+            Some(mesh) => NodeType::Mesh(Arc::new(ModelData {
+                model_id: self.models[mesh],
+                texture_id: None,
+                sampler: Sampler::default(),
+                shading,
+            })),
+            None => NodeType::Group,
+        };
+
+        let mut node = Node::new(
+            Box::new(|_node, _input_manager, _camera_manager, _delta_time| {}),
+            Transform::new(gltf_node.position, gltf_node.rotation, gltf_node.scale),
+            node_type);
+        for &child in &gltf_node.children {
+            node.add_child(self.instantiate_node(child, shading));
+        }
+        node
+    }
+}
+
+/// Merges the triangle primitives of a mesh into one model. Every vertex
+/// carries all of its attributes, so faces use the same index for each.
+fn model_from_mesh(mesh: &gltf::Mesh, buffers: &[gltf::buffer::Data], materials: &[MaterialId]) -> Model {
+    let mut model = Model {
+        verts: Vec::new(),
+        tex_coords: Vec::new(),
+        normals: Vec::new(),
+        faces: Vec::new(),
+        material_libraries: Vec::new(),
+        submeshes: Vec::new(),
+    };
+
+    for primitive in mesh.primitives() {
+        if primitive.mode() != gltf::mesh::Mode::Triangles {
+            warn!("Skipping {:?} primitive of mesh {}, only triangles are supported", primitive.mode(), mesh.index());
+            continue;
+        }
+
+        let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+        let Some(positions) = reader.read_positions() else {
+            continue;
+        };
+
+        let base = model.verts.len();
+        model.verts.extend(positions.map(Vec3::from_array));
+        let count = model.verts.len() - base;
+
+        // Only the base color map is read, so take the set it refers to.
+        // Rows of glTF images start at the top, OBJ's at the bottom, and
+        // the rasterizer expects the latter.
+        let tex_coord_set = primitive.material().pbr_metallic_roughness()
+            .base_color_texture()
+            .map_or(0, |info| info.tex_coord());
+        let tex_coords = reader.read_tex_coords(tex_coord_set)
+            .map(|tex_coords| tex_coords.into_f32().map(|[u, v]| Vec3::new(u, 1.0 - v, 0.0)).collect());
+        let normals = reader.read_normals()
+            .map(|normals| normals.map(Vec3::from_array).collect());
+        let has_tex_coords = tex_coords.is_some();
+        let has_normals = normals.is_some();
+        model.tex_coords.extend(tex_coords.unwrap_or_else(|| vec![Vec3::ZERO; count]));
+        model.normals.extend(normals.unwrap_or_else(|| vec![Vec3::ZERO; count]));
+
+        let indices: Vec<usize> = match reader.read_indices() {
+            Some(indices) => indices.into_u32().map(|i| i as usize).collect(),
+            None => (0..count).collect(),
+        };
+
+        let corner = |i: usize| FaceVertex {
+            vert: base + i,
+            tex_coord: has_tex_coords.then_some(base + i),
+            normal: has_normals.then_some(base + i),
+        };
+
+        let first_face = model.faces.len();
+        model.faces.extend(indices.chunks_exact(3)
+            .filter(|triangle| triangle.iter().all(|&i| i < count))
+            .map(|triangle| [corner(triangle[0]), corner(triangle[1]), corner(triangle[2])]));
+
+        let material = primitive.material();
+        model.submeshes.push(Submesh {
+            faces: first_face..model.faces.len(),
+            material_name: material.name().map(str::to_string),
+            material: material.index().map(|index| materials[index]),
+        });
+    }
+
+    model
+}
+
+/// Materials share one sampler between their maps, so this is only
+/// applied for the base color texture.
+fn sampler_from_gltf(sampler: &gltf::texture::Sampler) -> Sampler {
+    use gltf::texture::{MagFilter, MinFilter, WrappingMode};
+
+    let wrap = |mode| match mode {
+        WrappingMode::ClampToEdge => Wrap::Clamp,
+        WrappingMode::MirroredRepeat => Wrap::MirroredRepeat,
+        WrappingMode::Repeat => Wrap::Repeat,
+    };
+
+    Sampler {
+        filter: match sampler.mag_filter() {
+            Some(MagFilter::Nearest) => Filter::Nearest,
+            Some(MagFilter::Linear) | None => Filter::Bilinear,
+        },
+        mip_filter: match sampler.min_filter() {
+            Some(MinFilter::Nearest | MinFilter::Linear) => MipFilter::None,
+            Some(MinFilter::NearestMipmapNearest | MinFilter::LinearMipmapNearest) => MipFilter::Nearest,
+            Some(MinFilter::NearestMipmapLinear | MinFilter::LinearMipmapLinear) | None => MipFilter::Linear,
+        },
+        wrap_u: wrap(sampler.wrap_s()),
+        wrap_v: wrap(sampler.wrap_t()),
+    }
+}
+
+fn texture_from_image(image: &gltf::image::Data) -> Texture {
+    use gltf::image::Format;
+
+    // Expand to RGBA8, keeping the high byte of 16 bit channels
+    let (channels, bytes_per_channel) = match image.format {
+        Format::R8 => (1, 1),
+        Format::R8G8 => (2, 1),
+        Format::R8G8B8 => (3, 1),
+        Format::R8G8B8A8 => (4, 1),
+        Format::R16 => (1, 2),
+        Format::R16G16 => (2, 2),
+        Format::R16G16B16 => (3, 2),
+        Format::R16G16B16A16 => (4, 2),
+        Format::R32G32B32FLOAT => (3, 4),
+        Format::R32G32B32A32FLOAT => (4, 4),
+    };
+
+    let channel = |texel: &[u8], c: usize| -> u8 {
+        let bytes = &texel[c * bytes_per_channel..(c + 1) * bytes_per_channel];
+        match bytes_per_channel {
+            1 => bytes[0],
+            // gltf hands out 16 bit and float samples in native byte order
+            2 => (u16::from_ne_bytes([bytes[0], bytes[1]]) >> 8) as u8,
+            _ => (f32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]).clamp(0.0, 1.0) * 255.0).round() as u8,
+        }
+    };
+
+    let mut pixels = Vec::with_capacity(image.width as usize * image.height as usize * 4);
+    for texel in image.pixels.chunks_exact(channels * bytes_per_channel) {
+        let rgba = match channels {
+            // Grayscale, with alpha for two channels
+            1 | 2 => {
+                let luminance = channel(texel, 0);
+                let alpha = if channels == 2 { channel(texel, 1) } else { 255 };
+                [luminance, luminance, luminance, alpha]
+            },
+            3 => [channel(texel, 0), channel(texel, 1), channel(texel, 2), 255],
+            _ => [channel(texel, 0), channel(texel, 1), channel(texel, 2), channel(texel, 3)],
+        };
+        pixels.extend_from_slice(&rgba);
+    }
+
+    Texture::new(image.width as usize, image.height as usize, pixels)
+}
+
+#[derive(Debug)]
+pub struct GltfError {
+    pub path: PathBuf,
+    pub source: gltf::Error,
+}
+
+impl fmt::Display for GltfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.source)
+    }
+}
+
+impl std::error::Error for GltfError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
@@ -0,0 +1,39 @@
+//! A software rasterizer: OBJ and glTF models, textures, a scene graph and a
+//! renderer that draws them into its own framebuffer.
+//!
+//! The windowed viewer lives in the `rs-sloth-renderer` binary. Building
+//! with `default-features = false` drops winit and pixels, leaving a
+//! purely headless library.
+
+pub mod renderer;
+pub mod shader;
+pub mod light;
+pub mod model;
+pub mod texture;
+pub mod material;
+pub mod gltf_loader;
+pub mod camera;
+pub mod scene;
+pub mod scene_file;
+pub mod command;
+pub mod bindings;
+pub mod recording;
+pub mod timestep;
+pub mod behaviors;
+pub mod turntable;
+
+mod clip;
+
+pub use renderer::{Renderer, Color};
+pub use model::{Model, ModelId, ModelManager, ModelSource};
+pub use texture::{Filter, MipFilter, Sampler, Texture, TextureError, TextureId, TextureManager, Wrap};
+pub use material::{Material, MaterialId, MaterialManager};
+pub use gltf_loader::{GltfScene, GltfError};
+pub use camera::{Camera, CameraId, CameraManager, OrbitController};
+pub use scene::{Node, NodeType, Transform};
+pub use scene_file::{SceneError, SceneFile};
+pub use command::{InputManager, Key, MouseButton};
+pub use bindings::ActionBindings;
+pub use recording::{InputEvent, InputRecording, InputReplay};
+pub use turntable::{Spin, Turntable, TurntableError, TurntableOutput};
+pub use timestep::{Clock, FixedTimestep, FrameTiming, ManualClock, SystemClock};
@@ -0,0 +1,170 @@
+use glam::{Mat4, Vec3};
+use serde::{Deserialize, Serialize};
+
+/// Lights shine along the -Z axis of their node, like cameras look down it.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum LightKind {
+    /// Infinitely far away, only its direction matters
+    Directional,
+    /// Shines in all directions from the node's position
+    Point,
+    /// A cone around the node's -Z axis. Angles are in radians from the
+    /// axis; the light fades out between `inner_angle` and `outer_angle`.
+    Spot { inner_angle: f32, outer_angle: f32 },
+}
+
+/// Distance falloff of point and spot lights: 1 / (constant + linear * d + quadratic * d^2)
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Attenuation {
+    pub constant: f32,
+    pub linear: f32,
+    pub quadratic: f32,
+}
+
+impl Attenuation {
+    pub fn new(constant: f32, linear: f32, quadratic: f32) -> Self {
+        Self { constant, linear, quadratic }
+    }
+
+    fn factor(&self, distance: f32) -> f32 {
+        1.0 / (self.constant + self.linear * distance + self.quadratic * distance * distance).max(f32::EPSILON)
+    }
+}
+
+impl Default for Attenuation {
+    fn default() -> Self {
+        Self::new(1.0, 0.0, 0.0)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Light {
+    pub kind: LightKind,
+    pub color: Vec3,
+    pub intensity: f32,
+    #[serde(default)]
+    pub attenuation: Attenuation,
+}
+
+impl Light {
+    pub fn directional(color: Vec3, intensity: f32) -> Self {
+        Self {
+            kind: LightKind::Directional,
+            color,
+            intensity,
+            attenuation: Attenuation::default(),
+        }
+    }
+
+    pub fn point(color: Vec3, intensity: f32, attenuation: Attenuation) -> Self {
+        Self {
+            kind: LightKind::Point,
+            color,
+            intensity,
+            attenuation,
+        }
+    }
+
+    pub fn spot(color: Vec3, intensity: f32, attenuation: Attenuation, inner_angle: f32, outer_angle: f32) -> Self {
+        Self {
+            kind: LightKind::Spot { inner_angle, outer_angle },
+            color,
+            intensity,
+            attenuation,
+        }
+    }
+}
+
+/// A light resolved to world space for the current frame.
+#[derive(Debug, Clone, Copy)]
+pub struct SceneLight {
+    pub kind: LightKind,
+    /// Color scaled by intensity
+    pub radiance: Vec3,
+    pub attenuation: Attenuation,
+    pub position: Vec3,
+    /// Direction the light travels in
+    pub direction: Vec3,
+}
+
+impl SceneLight {
+    pub fn new(light: &Light, world_transform: Mat4) -> Self {
+        Self {
+            kind: light.kind,
+            radiance: light.color * light.intensity,
+            attenuation: light.attenuation,
+            position: world_transform.transform_point3(Vec3::ZERO),
+            direction: world_transform.transform_vector3(Vec3::NEG_Z).normalize_or_zero(),
+        }
+    }
+
+    /// Returns the direction from `position` towards the light and the
+    /// light arriving at `position`.
+    pub fn illuminate(&self, position: Vec3) -> (Vec3, Vec3) {
+        match self.kind {
+            LightKind::Directional => (-self.direction, self.radiance),
+            LightKind::Point => {
+                let (to_light, distance) = self.towards(position);
+                (to_light, self.radiance * self.attenuation.factor(distance))
+            },
+            LightKind::Spot { inner_angle, outer_angle } => {
+                let (to_light, distance) = self.towards(position);
+                let cos_angle = (-to_light).dot(self.direction);
+                let (cos_inner, cos_outer) = (inner_angle.cos(), outer_angle.cos());
+                let cone = ((cos_angle - cos_outer) / (cos_inner - cos_outer).max(f32::EPSILON)).clamp(0.0, 1.0);
+                (to_light, self.radiance * self.attenuation.factor(distance) * cone)
+            },
+        }
+    }
+
+    fn towards(&self, position: Vec3) -> (Vec3, f32) {
+        let offset = self.position - position;
+        let distance = offset.length();
+        (offset / distance.max(f32::EPSILON), distance)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A white spot light at the origin, shining down -Z, and the point one
+    /// unit away at `angle` from its axis.
+    fn spot_at(angle: f32) -> (SceneLight, Vec3) {
+        let light = Light::spot(Vec3::ONE, 2.0, Attenuation::default(), 0.2, 0.4);
+        (SceneLight::new(&light, Mat4::IDENTITY), Vec3::new(angle.sin(), 0.0, -angle.cos()))
+    }
+
+    #[test]
+    fn spot_is_full_inside_the_inner_cone() {
+        let (light, position) = spot_at(0.1);
+        let (to_light, radiance) = light.illuminate(position);
+        assert!(to_light.abs_diff_eq(-position, 1e-6));
+        assert!(radiance.abs_diff_eq(Vec3::splat(2.0), 1e-6), "{}", radiance);
+    }
+
+    #[test]
+    fn spot_fades_between_the_cones() {
+        let (light, position) = spot_at(0.3);
+        let expected = 2.0 * (0.3f32.cos() - 0.4f32.cos()) / (0.2f32.cos() - 0.4f32.cos());
+        let (_, radiance) = light.illuminate(position);
+        assert!(radiance.abs_diff_eq(Vec3::splat(expected), 1e-5), "{} != {}", radiance, expected);
+        assert!(expected > 0.0 && expected < 2.0);
+    }
+
+    #[test]
+    fn spot_is_dark_outside_the_outer_cone() {
+        let (light, position) = spot_at(0.5);
+        assert_eq!(light.illuminate(position).1, Vec3::ZERO);
+    }
+
+    #[test]
+    fn point_light_is_attenuated_with_distance() {
+        let light = Light::point(Vec3::new(1.0, 0.5, 0.0), 3.0, Attenuation::new(1.0, 0.5, 0.25));
+        let light = SceneLight::new(&light, Mat4::from_translation(Vec3::new(0.0, 2.0, 0.0)));
+        // 1 / (1 + 0.5 * 2 + 0.25 * 2^2) = 1 / 3
+        let (to_light, radiance) = light.illuminate(Vec3::ZERO);
+        assert!(to_light.abs_diff_eq(Vec3::Y, 1e-6));
+        assert!(radiance.abs_diff_eq(Vec3::new(1.0, 0.5, 0.0), 1e-6), "{}", radiance);
+    }
+}
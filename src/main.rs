@@ -1,116 +1,178 @@
-mod renderer;
-mod model;
-mod texture;
-mod camera;
-mod scene;
-mod command;
-mod behaviors;
-
 use std::sync::Arc;
 
-use behaviors::standard_camera_update;
-use glam::Vec3;
-use scene::{ModelData, Transform};
+use glam::{Vec2, Vec3};
+use log::{error, warn};
 use pixels::{Pixels, SurfaceTexture};
+mod cli;
+
+use rs_sloth_renderer::{
+    behaviors::standard_camera_update,
+    camera, model, material, renderer, scene, texture,
+    scene::{ModelData, Transform},
+    texture::{Sampler, Wrap},
+    light::Light,
+    command::{InputManager, Key, MouseButton},
+    bindings::ActionBindings,
+    recording::{InputEvent, InputRecording, InputReplay},
+    timestep::{FixedTimestep, SystemClock},
+    gltf_loader::GltfScene,
+    scene_file::SceneFile,
+    turntable::{Spin, Turntable, TurntableOutput},
+};
 use winit::{
-    event::{Event, WindowEvent},
+    event::{DeviceEvent, Event, MouseScrollDelta, WindowEvent},
     event_loop::{ControlFlow, EventLoop},
     window::WindowBuilder, dpi::LogicalSize,
 };
-use command::InputManager;
 
-const WIDTH: usize = 800;
-const HEIGHT: usize = 600;
 const WINDOW_TITLE: &str = "Sloth Engine";
+const BINDINGS_FILE: &str = "config/bindings.ron";
+/// Fixed rate of scene updates, in the viewer as well as when replaying
+const UPDATES_PER_SECOND: f32 = 60.0;
+/// Touchpads scroll in pixels, mouse wheels in lines
+const PIXELS_PER_LINE: f32 = 20.0;
 
 fn main() {
     env_logger::init();
 
-    let screenshot = std::env::args().any(|arg| arg == "--screenshot");
+    let options = cli::Options::parse(std::env::args().skip(1)).unwrap_or_else(|e| {
+        eprintln!("error: {}, see --help", e);
+        std::process::exit(2);
+    });
+    if options.help {
+        print!("{}", cli::USAGE);
+        return;
+    }
+    let (width, height) = (options.width, options.height);
 
     let mut model_manager = model::ModelManager::new();
     let mut texture_manager = texture::TextureManager::new();
+    let mut material_manager = material::MaterialManager::new();
     let mut camera_manager = camera::CameraManager::new();
 
-    let model_id = model_manager.load_model("objs/african_head.obj");
-    let texture_id = texture_manager.load_texture("objs/african_head_diffuse.tga");
-    let camera_id = camera_manager.add_camera(
-        camera::Camera::new(Vec3::new(0.0, 0.0, 8.0),
-                            Vec3::new(0.0, 0.0, -5.0),
-                            Vec3::new(0.0, 1.0, 0.0),
-                            45.0,
-                            WIDTH as f32 / HEIGHT as f32,
-                            0.1,
-                            100.0,
-                            0.05));
-    camera_manager.set_active_camera(camera_id);
-
-    let model_data = Arc::new(ModelData {
-        model_id,
-        texture_id,
-    });
-
-    let camera_data = Arc::new(scene::CameraEntityData {
-        camera: camera_id,
-        speed: 0.1,
-    });
-
-    //println!("model_data: {:?}", model_data);
-
     let position = glam::Vec3::new(0.0, 0.0, 0.0);
     let rotation = glam::Quat::from_rotation_y(0.0);
     let scale = glam::Vec3::new(1.0, 1.0, 1.0);
-    let mut scene_root = scene::Node::new(
-        Box::new(|_node, _input_manager, _delta_time| {}), 
-        Transform::new(position, rotation, scale), 
-        scene::NodeType::Group);
-
-    scene_root.add_child(
-        scene::Node::new(
-            Box::new(|_node, _input_manager, _delta_time| {}), 
-            Transform::new(
-                glam::Vec3::new(3.0, 0.0, -5.0), 
-                rotation, 
-                scale), 
-            scene::NodeType::Mesh(Arc::clone(&model_data))));
-
-    scene_root.add_child(
-        scene::Node::new(
-            Box::new(|_node, _input_manager, _delta_time| {}), 
-            Transform::new(
-                glam::Vec3::new(-3.0, 0.0, -2.0), 
-                rotation, 
-                scale), 
-            scene::NodeType::Mesh(Arc::clone(&model_data))));
-
-    scene_root.add_child(
-        scene::Node::new(
-            Box::new(|_node, _input_manager, _delta_time| {}), 
-            Transform::new(
-                glam::Vec3::new(-5.0, 2.0, -5.0), 
-                rotation, 
-                scale), 
-            scene::NodeType::Mesh(Arc::clone(&model_data))));
-
-    scene_root.add_child(
-        scene::Node::new(
-            Box::new(|node, input_manager, delta_time| {
-                standard_camera_update(node, input_manager, delta_time)
-            }), 
-            Transform::new(
-                glam::Vec3::new(0.0, 0.0, -5.0), 
-                rotation, 
-                scale), 
-            scene::NodeType::Camera(Arc::clone(&camera_data))));
-
-    /*
-    scene_root.add_child(scene::Node::new(standard_camera_update, glam::Mat4::from_translation(glam::Vec3::new(0.0, 0.0, -5.0)), scene::NodeType::Camera(Arc::clone(&camera_data))));
-    */
+
+    let mut scene_root = match &options.scene {
+        Some(scene_file) => SceneFile::load_scene(scene_file, &mut model_manager, &mut texture_manager, &mut material_manager, &mut camera_manager).unwrap_or_else(|e| {
+            error!("Failed to load scene: {}", e);
+            std::process::exit(1);
+        }),
+        None => {
+            let mut scene_root = scene::Node::new(
+                Box::new(|_node, _input_manager, _camera_manager, _delta_time| {}), 
+                Transform::new(position, rotation, scale), 
+                scene::NodeType::Group);
+            add_meshes(&mut scene_root, &options, &mut model_manager, &mut texture_manager, &mut material_manager);
+
+            // Points down -Z, the same way the camera looks
+            scene_root.add_child(
+                scene::Node::new(
+                    Box::new(|_node, _input_manager, _camera_manager, _delta_time| {}),
+                    Transform::new(position, rotation, scale),
+                    scene::NodeType::Light(Arc::new(Light::directional(Vec3::ONE, 1.0)))));
+            scene_root
+        },
+    };
+
+    match camera_manager.get_active_camera_mut() {
+        Some(camera) => camera.aspect_ratio = width as f32 / height as f32,
+        None => {
+            let camera_id = camera_manager.add_camera(
+                camera::Camera::new(options.camera_position,
+                                    options.camera_target,
+                                    Vec3::new(0.0, 1.0, 0.0),
+                                    options.fov.to_radians(),
+                                    width as f32 / height as f32,
+                                    0.1,
+                                    100.0,
+                                    0.05));
+            camera_manager.set_active_camera(camera_id);
+
+            let camera_data = Arc::new(scene::CameraEntityData {
+                camera: camera_id,
+                speed: 5.0,
+            });
+            scene_root.add_child(
+                scene::Node::new(
+                    Box::new(standard_camera_update),
+                    Transform::new(options.camera_position, rotation, scale),
+                    scene::NodeType::Camera(Arc::clone(&camera_data))));
+        },
+    }
+
+    let mut renderer = renderer::Renderer::new(width, height);
+    renderer.set_thread_count(0);
+
+    let mut input_manager = InputManager::new();
+    match ActionBindings::load(BINDINGS_FILE) {
+        Ok(bindings) => input_manager.set_bindings(bindings),
+        Err(e) => warn!("Failed to load bindings, using the default controls: {}", e),
+    }
+    let orbit_controller = camera::OrbitController::new();
+
+    if let Some(frames) = options.turntable {
+        let spin = options.turntable_node.clone().map_or(Spin::Camera, Spin::Node);
+        let mut turntable = Turntable::new(frames, spin);
+        turntable.frames_per_second = options.gif_fps;
+        let output = TurntableOutput { frame_directory: options.frame_dir.clone(), gif: options.gif.clone() };
+
+        println!("Rendering a {} frame turntable...", frames);
+        turntable.export(&output, &mut renderer, &mut scene_root, &model_manager, &texture_manager, &material_manager, &mut camera_manager).unwrap_or_else(|e| {
+            error!("Failed to export turntable: {}", e);
+            std::process::exit(1);
+        });
+        save_scene(&options, &scene_root, &model_manager, &texture_manager, &camera_manager);
+        return;
+    }
+
+    if let Some(output) = &options.output {
+        println!("Rendering to {}...", output.display());
+        renderer.render(&scene_root, &model_manager, &texture_manager, &material_manager, &camera_manager);
+        renderer.save(output).unwrap_or_else(|e| {
+            error!("Failed to save {}: {}", output.display(), e);
+            std::process::exit(1);
+        });
+        save_scene(&options, &scene_root, &model_manager, &texture_manager, &camera_manager);
+        return;
+    }
+
+    if let Some(replay_file) = &options.replay {
+        let recording = InputRecording::load(replay_file).unwrap_or_else(|e| {
+            error!("Failed to load recording: {}", e);
+            std::process::exit(1);
+        });
+        let frame_dir = options.frame_dir.clone().unwrap_or_default();
+        std::fs::create_dir_all(&frame_dir).unwrap_or_else(|e| {
+            error!("Failed to create {}: {}", frame_dir.display(), e);
+            std::process::exit(1);
+        });
+
+        // Recordings hold one frame per fixed update, so replaying renders
+        // the state after every update the viewer ran. Frames are numbered
+        // from 0, like the turntable's.
+        let mut replay = InputReplay::new(recording);
+        let mut frame = 0;
+        while replay.play_frame(&mut input_manager) {
+            update(&mut scene_root, &mut input_manager, &orbit_controller, &mut camera_manager, 1.0 / UPDATES_PER_SECOND);
+            renderer.render(&scene_root, &model_manager, &texture_manager, &material_manager, &camera_manager);
+            let frame_path = frame_dir.join(format!("frame_{:05}.png", frame));
+            renderer.save(&frame_path).unwrap_or_else(|e| {
+                error!("Failed to save {}: {}", frame_path.display(), e);
+                std::process::exit(1);
+            });
+            frame += 1;
+        }
+        println!("Replayed {} frames into {}", replay.frame(), frame_dir.display());
+        save_scene(&options, &scene_root, &model_manager, &texture_manager, &camera_manager);
+        return;
+    }
 
     let event_loop = EventLoop::new();
     let window = {
-        let size = LogicalSize::new(WIDTH as f64, HEIGHT as f64);
-        let scaled_size = LogicalSize::new(WIDTH as f64 * 3.0, HEIGHT as f64 * 3.0);
+        let size = LogicalSize::new(width as f64, height as f64);
+        let scaled_size = LogicalSize::new(width as f64 * 3.0, height as f64 * 3.0);
 
         WindowBuilder::new()
             .with_title(WINDOW_TITLE)
@@ -123,82 +185,163 @@ fn main() {
     let mut pixels = {
         let window_size = window.inner_size();
         let surface_texture = SurfaceTexture::new(window_size.width, window_size.height, &window);
-        Pixels::new(WIDTH as u32, HEIGHT as u32, surface_texture).unwrap()
+        Pixels::new(width as u32, height as u32, surface_texture).unwrap()
     };
 
-    let mut renderer = renderer::Renderer::new(WIDTH, HEIGHT);
-
-    if screenshot {
-        println!("Taking screenshot...");
-        renderer.render_scene(&scene_root, &model_manager, &texture_manager, &camera_manager, &mut pixels, screenshot);
+    let mut timestep = FixedTimestep::new(SystemClock::new(), UPDATES_PER_SECOND);
+    timestep.set_frame_cap(options.max_fps);
+    // Camera before the latest update, rendering blends from it
+    let mut previous_camera_pose = camera_pose(&camera_manager);
+    let mut alpha = 1.0;
+    if options.record.is_some() {
+        input_manager.start_recording();
+    }
 
-    } else {
-        let start_time = std::time::Instant::now();
-        let animation_duration = std::time::Duration::from_secs(10);
-        let mut last_frame_start = start_time;
-        let mut input_manager = InputManager::new();
-
-        event_loop.run(move |event, _, control_flow| {
-            *control_flow = ControlFlow::Poll;
-
-            match event {
-                Event::WindowEvent { event, .. } => match event {
-                    WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
-                    WindowEvent::MouseInput { device_id, state, button, modifiers } => {
-                        // TODO: Handle mouse movement
-                        match button {
-                            winit::event::MouseButton::Left => {
-                                if state == winit::event::ElementState::Pressed {
-                                    println!("Left mouse button pressed");
-                                } else {
-                                    println!("Left mouse button released");
-                                }
-                            },
-                            winit::event::MouseButton::Right => {
-                                if state == winit::event::ElementState::Pressed {
-                                    println!("Right mouse button pressed");
-                                } else {
-                                    println!("Right mouse button released");
-                                }
-                            },
-                            winit::event::MouseButton::Middle => {
-                                if state == winit::event::ElementState::Pressed {
-                                    println!("Middle mouse button pressed");
-                                } else {
-                                    println!("Middle mouse button released");
-                                }
-                            },
-                            winit::event::MouseButton::Other(_) => (),
-                        }
-                    },
-                    WindowEvent::CursorMoved { device_id, position, modifiers } => {
-                        println!("Cursor moved: {:?}", position);
-                        let mut camera = camera_manager.get_active_camera_mut();
-                        match camera {
-                            Some(camera) => {
-                            },
-                            None => (),
+    event_loop.run(move |event, _, control_flow| {
+        match event {
+            Event::WindowEvent { event, .. } => match event {
+                WindowEvent::CloseRequested => {
+                    if let (Some(recording), Some(record_file)) = (input_manager.stop_recording(), &options.record) {
+                        match recording.save(record_file) {
+                            Ok(()) => println!("Recorded {} frames to {}", recording.frame_count, record_file.display()),
+                            Err(e) => error!("Failed to save recording: {}", e),
                         }
-                    },
-                    WindowEvent::KeyboardInput { device_id, input, is_synthetic } => {
-                        if let Some(keycode) = input.virtual_keycode {
-                            let is_pressed = input.state == winit::event::ElementState::Pressed;
-                            input_manager.set_key_pressed(keycode, is_pressed);
-                        }
-                    },
-                    _ => (), 
+                    }
+                    save_scene(&options, &scene_root, &model_manager, &texture_manager, &camera_manager);
+                    *control_flow = ControlFlow::Exit;
+                },
+                WindowEvent::MouseInput { state, button, .. } => {
+                    let is_pressed = state == winit::event::ElementState::Pressed;
+                    input_manager.handle_event(InputEvent::MouseButton { button: MouseButton::from_winit(button), pressed: is_pressed });
                 },
-                Event::RedrawRequested(_) => {
-                    let delta = start_time.elapsed();
-                    renderer.render_scene(&scene_root, &model_manager, &texture_manager, &camera_manager, &mut pixels, screenshot);
-                    let time_since_last_frame = last_frame_start.elapsed();
-                    //println!("FPS: {}", 1.0 / time_since_last_frame.as_secs_f32());
-                    last_frame_start = std::time::Instant::now();
+                WindowEvent::CursorMoved { position, .. } => {
+                    input_manager.handle_event(InputEvent::CursorMoved(Vec2::new(position.x as f32, position.y as f32)));
+                },
+                WindowEvent::MouseWheel { delta, .. } => {
+                    let lines = match delta {
+                        MouseScrollDelta::LineDelta(x, y) => Vec2::new(x, y),
+                        MouseScrollDelta::PixelDelta(position) => Vec2::new(position.x as f32, position.y as f32) / PIXELS_PER_LINE,
+                    };
+                    input_manager.handle_event(InputEvent::Scroll(lines));
+                },
+                WindowEvent::KeyboardInput { input, .. } => {
+                    if let Some(key) = input.virtual_keycode.and_then(Key::from_winit) {
+                        let is_pressed = input.state == winit::event::ElementState::Pressed;
+                        input_manager.handle_event(InputEvent::Key { key, pressed: is_pressed });
+                    }
+                },
+                _ => (), 
+            },
+            Event::DeviceEvent { event: DeviceEvent::MouseMotion { delta: (dx, dy) }, .. } => {
+                input_manager.handle_event(InputEvent::MouseMotion(Vec2::new(dx as f32, dy as f32)));
+            },
+            Event::MainEventsCleared => {
+                if let Some(timing) = timestep.begin_frame() {
+                    for _ in 0..timing.updates {
+                        previous_camera_pose = camera_pose(&camera_manager);
+                        update(&mut scene_root, &mut input_manager, &orbit_controller, &mut camera_manager, timestep.delta_time());
+                    }
+                    alpha = timing.alpha;
+                    window.request_redraw();
                 }
-                _ => (),
-            }
 
-            window.request_redraw();
+                let next_frame = timestep.clock().instant_at(timestep.next_frame_time());
+                *control_flow = ControlFlow::WaitUntil(next_frame);
+            },
+            Event::RedrawRequested(_) => {
+                // Render between the last two updates, so motion stays smooth
+                // when frames and updates don't line up
+                let current_camera_pose = camera_pose(&camera_manager);
+                set_camera_pose(&mut camera_manager, previous_camera_pose, current_camera_pose, alpha);
+                renderer.render_scene(&scene_root, &model_manager, &texture_manager, &material_manager, &camera_manager, &mut pixels);
+                set_camera_pose(&mut camera_manager, current_camera_pose, current_camera_pose, 1.0);
+            },
+            _ => (),
+        }
+    });
+}
+
+/// Places the model from the command line once per `--mesh`.
+fn add_meshes(scene_root: &mut scene::Node,
+              options: &cli::Options,
+              model_manager: &mut model::ModelManager,
+              texture_manager: &mut texture::TextureManager,
+              material_manager: &mut material::MaterialManager) {
+    if options.is_gltf() {
+        let gltf_scene = GltfScene::load(&options.model, model_manager, texture_manager, material_manager).unwrap_or_else(|e| {
+            error!("Failed to load model: {}", e);
+            std::process::exit(1);
         });
+        if options.texture.is_some() {
+            warn!("--texture only applies to OBJ models, glTF materials bring their own");
+        }
+        for &transform in &options.meshes {
+            let mut instance = gltf_scene.instantiate(options.shading);
+            instance.transformation = transform;
+            scene_root.add_child(instance);
+        }
+    } else {
+        let model_id = model_manager.load_model_with_materials(&options.model, texture_manager, material_manager).unwrap_or_else(|e| {
+            error!("Failed to load model: {}", e);
+            std::process::exit(1);
+        });
+        let texture_id = options.texture.as_ref().map(|texture| {
+            texture_manager.load_texture(texture).unwrap_or_else(|e| {
+                error!("Failed to load texture: {}", e);
+                std::process::exit(1);
+            })
+        });
+        let model_data = Arc::new(ModelData {
+            model_id,
+            texture_id,
+            sampler: Sampler::trilinear(Wrap::Clamp),
+            shading: options.shading,
+        });
+        for &transform in &options.meshes {
+            scene_root.add_child(
+                scene::Node::new(
+                    Box::new(|_node, _input_manager, _camera_manager, _delta_time| {}),
+                    transform,
+                    scene::NodeType::Mesh(Arc::clone(&model_data))));
+        }
+    }
+}
+
+/// Writes the scene out if asked to with `--save-scene`.
+fn save_scene(options: &cli::Options,
+              scene_root: &scene::Node,
+              model_manager: &model::ModelManager,
+              texture_manager: &texture::TextureManager,
+              camera_manager: &camera::CameraManager) {
+    if let Some(scene_file) = &options.save_scene {
+        match SceneFile::save_scene(scene_file, scene_root, model_manager, texture_manager, camera_manager) {
+            Ok(()) => println!("Saved the scene to {}", scene_file.display()),
+            Err(e) => error!("Failed to save scene: {}", e),
+        }
+    }
+}
+
+/// Moves the cameras and nodes on by one frame.
+fn update(scene_root: &mut scene::Node,
+          input_manager: &mut InputManager,
+          orbit_controller: &camera::OrbitController,
+          camera_manager: &mut camera::CameraManager,
+          delta_time: f32) {
+    if let Some(camera) = camera_manager.get_active_camera_mut() {
+        orbit_controller.update(input_manager, camera);
+    }
+    scene_root.update(input_manager, camera_manager, delta_time);
+    input_manager.end_frame();
+}
+
+/// Position and target of the active camera.
+fn camera_pose(camera_manager: &camera::CameraManager) -> Option<(Vec3, Vec3)> {
+    camera_manager.get_active_camera().map(|camera| (camera.position, camera.target))
+}
+
+fn set_camera_pose(camera_manager: &mut camera::CameraManager, from: Option<(Vec3, Vec3)>, to: Option<(Vec3, Vec3)>, alpha: f32) {
+    if let (Some(camera), Some(from), Some(to)) = (camera_manager.get_active_camera_mut(), from, to) {
+        camera.position = from.0.lerp(to.0, alpha);
+        camera.target = from.1.lerp(to.1, alpha);
     }
 }
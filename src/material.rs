@@ -0,0 +1,175 @@
+use std::{collections::HashMap, path::Path};
+
+use glam::Vec3;
+use log::info;
+
+use crate::{model::{self, ObjError, ParseErrorKind}, texture::{Sampler, TextureId, TextureManager, Wrap}};
+
+/// Surface description from a Wavefront MTL file.
+#[derive(Debug, Clone)]
+pub struct Material {
+    pub name: String,
+    /// `Kd`
+    pub diffuse: Vec3,
+    /// `Ks`
+    pub specular: Vec3,
+    /// `Ns`, the specular exponent
+    pub shininess: f32,
+    /// `d`, 1 is fully opaque
+    pub opacity: f32,
+    /// `map_Kd`
+    pub diffuse_map: Option<TextureId>,
+    /// `map_Bump` or `bump`. Loaded, but not used by the built-in shaders.
+    pub bump_map: Option<TextureId>,
+    /// `map_Ks`
+    pub specular_map: Option<TextureId>,
+    /// How the maps are read, trilinear by default. Wraps as `map_Kd`'s
+    /// `-clamp` option says, whatever the other maps ask for.
+    pub sampler: Sampler,
+}
+
+impl Material {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            diffuse: Vec3::ONE,
+            specular: Vec3::ZERO,
+            shininess: 1.0,
+            opacity: 1.0,
+            diffuse_map: None,
+            bump_map: None,
+            specular_map: None,
+            sampler: Sampler::trilinear(Wrap::Repeat),
+        }
+    }
+
+    /// Reads every material in an MTL file. Texture maps are loaded into
+    /// `texture_manager`, relative to the MTL file's directory.
+    pub fn load_library<P: AsRef<Path>>(library_filename: P, texture_manager: &mut TextureManager) -> Result<Vec<Material>, ObjError> {
+        let path = library_filename.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .map_err(|source| ObjError::Io { path: path.to_path_buf(), source })?;
+        let directory = path.parent().unwrap_or(Path::new(""));
+
+        let mut materials: Vec<Material> = Vec::new();
+
+        for (line_index, line) in contents.lines().enumerate() {
+            let error = |kind| ObjError::Parse { path: path.to_path_buf(), line: line_index + 1, kind };
+
+            let values: Vec<&str> = line.split_whitespace().collect();
+            let Some(keyword) = values.first() else {
+                continue;
+            };
+            if keyword.starts_with('#') {
+                continue;
+            }
+
+            if *keyword == "newmtl" {
+                let name = values.get(1).ok_or_else(|| error(ParseErrorKind::MissingComponents { expected: 1, found: 0 }))?;
+                materials.push(Material::new(name));
+                continue;
+            }
+
+            let Some(material) = materials.last_mut() else {
+                return Err(error(ParseErrorKind::NoCurrentMaterial(keyword.to_string())));
+            };
+
+            match *keyword {
+                "Kd" => material.diffuse = model::parse_vec3(&values, 3).map_err(error)?,
+                "Ks" => material.specular = model::parse_vec3(&values, 3).map_err(error)?,
+                "Ns" => material.shininess = model::parse_vec3(&values, 1).map_err(error)?.x,
+                "d" => material.opacity = model::parse_vec3(&values, 1).map_err(error)?.x,
+                "map_Kd" | "map_Ks" | "map_Bump" | "map_bump" | "bump" => {
+                    // MTL maps repeat unless told `-clamp on`
+                    if *keyword == "map_Kd" {
+                        let clamp = values.windows(2).any(|option| option == ["-clamp", "on"]);
+                        material.sampler.wrap_u = if clamp { Wrap::Clamp } else { Wrap::Repeat };
+                        material.sampler.wrap_v = material.sampler.wrap_u;
+                    }
+
+                    // Options like `-bm 0.5` come before the file name
+                    let filename = values[1..].last()
+                        .ok_or_else(|| error(ParseErrorKind::MissingComponents { expected: 1, found: 0 }))?;
+                    let texture_path = directory.join(filename);
+                    let texture = texture_manager.load_texture(&texture_path)
+                        .map_err(|source| ObjError::Texture { path: path.to_path_buf(), line: line_index + 1, source })?;
+                    match *keyword {
+                        "map_Kd" => material.diffuse_map = Some(texture),
+                        "map_Ks" => material.specular_map = Some(texture),
+                        _ => material.bump_map = Some(texture),
+                    }
+                },
+                // Ambient and emissive colors, illumination models and the like
+                _ => continue,
+            }
+        }
+
+        info!("Material library loaded: {} materials", materials.len());
+        Ok(materials)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MaterialId(usize);
+
+pub struct MaterialManager {
+    materials: HashMap<MaterialId, Material>,
+}
+
+impl Default for MaterialManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MaterialManager {
+    pub fn new() -> Self {
+        Self {
+            materials: HashMap::new(),
+        }
+    }
+
+    pub fn add_material(&mut self, material: Material) -> MaterialId {
+        let id = MaterialId(self.materials.len());
+        self.materials.insert(id, material);
+        id
+    }
+
+    pub fn get_material(&self, id: MaterialId) -> &Material {
+        self.materials.get(&id).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec2;
+
+    use super::*;
+    use crate::shader::Surface;
+
+    /// A diffuse map seen from far enough away that each pixel covers the
+    /// whole 4x4 checkerboard reads the 1x1 level, the board's average.
+    #[test]
+    fn minified_map_reads_a_smaller_level() {
+        let directory = std::env::temp_dir().join(format!("rs_sloth_renderer_{}_material", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let checkerboard = image::RgbaImage::from_fn(4, 4, |x, y| {
+            image::Rgba(if (x + y) % 2 == 0 { [255, 0, 0, 255] } else { [255, 255, 255, 255] })
+        });
+        checkerboard.save_with_format(directory.join("checker.png"), image::ImageFormat::Png).unwrap();
+        std::fs::write(directory.join("checker.mtl"), "newmtl checker\nmap_Kd checker.png\n").unwrap();
+
+        let mut textures = TextureManager::new();
+        let result = Material::load_library(directory.join("checker.mtl"), &mut textures);
+        std::fs::remove_dir_all(&directory).unwrap();
+        let materials = result.unwrap();
+
+        let texture = textures.get_texture(materials[0].diffuse_map.unwrap());
+        let surface = Surface::from_material(&materials[0], |id| textures.get_texture(id));
+        let (color, _) = surface.diffuse_at(Vec2::splat(0.125), Vec2::new(1.0, 0.0), Vec2::new(0.0, 1.0));
+        let smallest = texture.level(texture.mips.len());
+        let expected = smallest.sample(Vec2::ZERO, &materials[0].sampler).to_vec3();
+        assert!(color.abs_diff_eq(expected, 1e-6), "{} != {}", color, expected);
+        assert!(expected.y > 0.4 && expected.y < 0.6, "{}", expected);
+    }
+}
@@ -1,34 +1,105 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, fmt, ops::Range, path::{Path, PathBuf}};
 
-use log::info;
+use log::{info, warn};
 
 use glam::Vec3;
 
+use crate::{material::{Material, MaterialId, MaterialManager}, texture::{TextureError, TextureManager}};
+
 #[derive(Debug)]
 pub struct Model {
     pub verts: Vec<Vec3>,
     pub tex_coords: Vec<Vec3>,
     pub normals: Vec<Vec3>,
-    pub faces: Vec<Vec<(i32,i32,i32)>>,
+    /// Polygons are split into triangles when loading
+    pub faces: Vec<[FaceVertex; 3]>,
+    /// `mtllib` files, relative to the working directory
+    pub material_libraries: Vec<PathBuf>,
+    /// Runs of faces sharing a material, in face order. Covers every face.
+    pub submeshes: Vec<Submesh>,
+}
+
+/// The faces between two `usemtl` statements.
+#[derive(Debug, Clone)]
+pub struct Submesh {
+    pub faces: Range<usize>,
+    /// Name given to `usemtl`, `None` before the first one
+    pub material_name: Option<String>,
+    /// Set by `ModelManager::load_model_with_materials`
+    pub material: Option<MaterialId>,
+}
+
+/// One corner of a face, as 0-based indices into the model's attributes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FaceVertex {
+    pub vert: usize,
+    pub tex_coord: Option<usize>,
+    pub normal: Option<usize>,
 }
 
 #[derive(Debug, Hash, Eq, PartialEq, Copy, Clone)]
 pub struct ModelId(usize);
 
+/// Where a model was loaded from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModelSource {
+    pub path: PathBuf,
+    /// Index of the mesh within a glTF file, `None` for OBJ files
+    pub mesh: Option<usize>,
+}
+
 pub struct ModelManager {
     pub models: HashMap<ModelId, Model>,
+    sources: HashMap<ModelId, ModelSource>,
+}
+
+impl Default for ModelManager {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl ModelManager {
     pub fn new() -> Self {
         Self {
             models: HashMap::new(),
+            sources: HashMap::new(),
         }
     }
 
-    pub fn load_model(&mut self, model_filename: &str) -> ModelId {
-        let model = Model::new(model_filename);
-        self.add_model(model)
+    pub fn load_model<P: AsRef<Path>>(&mut self, model_filename: P) -> Result<ModelId, ObjError> {
+        let model = Model::load(&model_filename)?;
+        let source = ModelSource { path: model_filename.as_ref().to_path_buf(), mesh: None };
+        Ok(self.add_model_from(model, source))
+    }
+
+    /// Loads the model along with its `mtllib` files, registering their
+    /// materials and texture maps and binding them to the submeshes.
+    pub fn load_model_with_materials<P: AsRef<Path>>(&mut self,
+                                                     model_filename: P,
+                                                     texture_manager: &mut TextureManager,
+                                                     material_manager: &mut MaterialManager) -> Result<ModelId, ObjError> {
+        let mut model = Model::load(&model_filename)?;
+
+        let mut materials: HashMap<String, MaterialId> = HashMap::new();
+        for library in &model.material_libraries {
+            for material in Material::load_library(library, texture_manager)? {
+                let name = material.name.clone();
+                materials.insert(name, material_manager.add_material(material));
+            }
+        }
+
+        for submesh in &mut model.submeshes {
+            if let Some(name) = &submesh.material_name {
+                submesh.material = materials.get(name).copied();
+                if submesh.material.is_none() {
+                    warn!("Material `{}` not found in any material library", name);
+                }
+            }
+        }
+
+        let source = ModelSource { path: model_filename.as_ref().to_path_buf(), mesh: None };
+        Ok(self.add_model_from(model, source))
     }
 
     pub fn add_model(&mut self, model: Model) -> ModelId {
@@ -37,71 +108,350 @@ impl ModelManager {
         id
     }
 
+    /// Adds a model read from `source` by a loader of its own.
+    pub fn add_model_from(&mut self, model: Model, source: ModelSource) -> ModelId {
+        let id = self.add_model(model);
+        self.sources.insert(id, source);
+        id
+    }
+
     pub fn get_model(&self, id: ModelId) -> &Model {
         self.models.get(&id).unwrap()
     }
+
+    /// The file the model was loaded from, `None` for models added
+    /// directly.
+    pub fn get_model_path(&self, id: ModelId) -> Option<&Path> {
+        self.sources.get(&id).map(|source| source.path.as_path())
+    }
+
+    /// Like `get_model_path`, along with the mesh for glTF files.
+    pub fn get_model_source(&self, id: ModelId) -> Option<&ModelSource> {
+        self.sources.get(&id)
+    }
 }
 
 impl Model {
-    pub fn new(model_filename: &str) -> Self {
-        let mut verts: Vec<Vec3> = Vec::new();
-        let mut tex_coords: Vec<Vec3> = Vec::new();
-        let mut normals: Vec<Vec3> = Vec::new();
-        let mut faces: Vec<Vec<(i32,i32,i32)>> = Vec::new();
+    /// Loads a Wavefront OBJ file. Faces may use any of the `v`, `v/vt`,
+    /// `v//vn` and `v/vt/vn` forms with absolute or negative (relative)
+    /// indices, and polygons, concave ones too, get split into triangles.
+    pub fn load<P: AsRef<Path>>(model_filename: P) -> Result<Self, ObjError> {
+        let path = model_filename.as_ref();
 
         // Validate model_filename is obj
-        if !model_filename.ends_with(".obj") {
-            panic!("Invalid file type. Must be .obj");
+        if !path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("obj")) {
+            return Err(ObjError::UnsupportedExtension { path: path.to_path_buf() });
         }
 
-        // Read file
-        let contents = std::fs::read_to_string(model_filename)
-            .expect("Something went wrong reading the file");
+        let contents = std::fs::read_to_string(path)
+            .map_err(|source| ObjError::Io { path: path.to_path_buf(), source })?;
 
+        let model = Self::parse(&contents, path)?;
+        info!("Model loaded: {} verts, {} faces", model.verts.len(), model.faces.len());
+        Ok(model)
+    }
+
+    fn parse(contents: &str, path: &Path) -> Result<Self, ObjError> {
+        let mut verts: Vec<Vec3> = Vec::new();
+        let mut tex_coords: Vec<Vec3> = Vec::new();
+        let mut normals: Vec<Vec3> = Vec::new();
+        let mut faces: Vec<[FaceVertex; 3]> = Vec::new();
+        let mut material_libraries: Vec<PathBuf> = Vec::new();
+        let mut submeshes: Vec<Submesh> = Vec::new();
+        let directory = path.parent().unwrap_or(Path::new(""));
+
+        for (line_index, line) in contents.lines().enumerate() {
+            let error = |kind| ObjError::Parse { path: path.to_path_buf(), line: line_index + 1, kind };
 
-        // for each line in contents, read the line as Vec3
-        for line in contents.split("\n") {
-            if line.starts_with("#") || line.is_empty() {
-                continue;
-            }
             let values: Vec<&str> = line.split_whitespace().collect();
-            if values[0].eq("v") {
-                let x = values[1].parse::<f32>().unwrap();
-                let y = values[2].parse::<f32>().unwrap();
-                let z = values[3].parse::<f32>().unwrap();
-                let vert: Vec3 = Vec3 { x, y, z };
-                verts.push(vert);
-            }
-            else if values[0].eq("vt") {
-                let x = values[1].parse::<f32>().unwrap();
-                let y = values[2].parse::<f32>().unwrap();
-                let z = values[3].parse::<f32>().unwrap();
-                let tex: Vec3 = Vec3 { x, y, z };
-                tex_coords.push(tex);
-            } 
-            else if values[0].eq("vn") {
-                let x = values[1].parse::<f32>().unwrap();
-                let y = values[2].parse::<f32>().unwrap();
-                let z = values[3].parse::<f32>().unwrap();
-                let norm: Vec3 = Vec3 { x, y, z };
-                normals.push(norm);
-            }
-            else if values[0].eq("f") {
-                // f 1/1/1 2/2/2 3/3/3
-                let mut face: Vec<(i32,i32,i32)> = Vec::new();
-                for i in 1..values.len() {
-                    let face_values: Vec<&str> = values[i].split("/").collect();
-                    let vert_index = face_values[0].parse::<i32>().unwrap();
-                    let tex_index = face_values[1].parse::<i32>().unwrap();
-                    let norm_index = face_values[2].parse::<i32>().unwrap();
-                    face.push((vert_index - 1, tex_index - 1, norm_index - 1));
-                }
-                faces.push(face);
+            match values.first() {
+                None => continue,
+                Some(keyword) if keyword.starts_with('#') => continue,
+                // v x y z [w]
+                Some(&"v") => verts.push(parse_vec3(&values, 3).map_err(error)?),
+                // vt u [v [w]]
+                Some(&"vt") => tex_coords.push(parse_vec3(&values, 1).map_err(error)?),
+                // vn x y z
+                Some(&"vn") => normals.push(parse_vec3(&values, 3).map_err(error)?),
+                Some(&"f") => {
+                    // f 1/1/1 2/2/2 3/3/3 ...
+                    if values.len() < 4 {
+                        return Err(error(ParseErrorKind::DegenerateFace(values.len() - 1)));
+                    }
+
+                    let mut polygon: Vec<FaceVertex> = Vec::new();
+                    for value in values.iter().skip(1) {
+                        let face_vertex = parse_face_vertex(value, verts.len(), tex_coords.len(), normals.len())
+                            .map_err(error)?;
+                        polygon.push(face_vertex);
+                    }
+
+                    faces.extend(triangulate(&polygon, &verts));
+                },
+                // mtllib file1.mtl [file2.mtl ...]
+                Some(&"mtllib") => {
+                    if values.len() < 2 {
+                        return Err(error(ParseErrorKind::MissingComponents { expected: 1, found: 0 }));
+                    }
+                    material_libraries.extend(values.iter().skip(1).map(|library| directory.join(library)));
+                },
+                // usemtl name
+                Some(&"usemtl") => {
+                    let name = values.get(1)
+                        .ok_or_else(|| error(ParseErrorKind::MissingComponents { expected: 1, found: 0 }))?;
+                    Self::close_submesh(&mut submeshes, faces.len());
+                    submeshes.push(Submesh {
+                        faces: faces.len()..faces.len(),
+                        material_name: Some(name.to_string()),
+                        material: None,
+                    });
+                },
+                // Groups, smoothing groups and the like
+                Some(_) => continue,
             }
         }
 
-        info!("Model loaded: {} verts, {} faces", verts.len(), faces.len());
+        Self::close_submesh(&mut submeshes, faces.len());
+        submeshes.retain(|submesh| !submesh.faces.is_empty());
+
+        Ok(Self { verts, tex_coords, normals, faces, material_libraries, submeshes })
+    }
+
+    /// Ends the current submesh at `face_count`, starting an unnamed one if
+    /// faces came before the first `usemtl`.
+    fn close_submesh(submeshes: &mut Vec<Submesh>, face_count: usize) {
+        match submeshes.last_mut() {
+            Some(submesh) => submesh.faces.end = face_count,
+            None => submeshes.push(Submesh { faces: 0..face_count, material_name: None, material: None }),
+        }
+    }
+}
+
+/// Splits a polygon into triangles by ear clipping, keeping its winding.
+/// Convex polygons come out as a fan around the first corner.
+fn triangulate(polygon: &[FaceVertex], verts: &[Vec3]) -> Vec<[FaceVertex; 3]> {
+    let positions: Vec<Vec3> = polygon.iter().map(|corner| verts[corner.vert]).collect();
+    // Newell's method, which points the normal the way the polygon winds
+    // even when some of its corners are concave
+    let normal = positions.iter()
+        .zip(positions.iter().cycle().skip(1))
+        .fold(Vec3::ZERO, |normal, (a, b)| normal + a.cross(*b));
+    let turns_left = |a: Vec3, b: Vec3, c: Vec3| (b - a).cross(c - b).dot(normal) > 0.0;
+
+    let mut remaining: Vec<usize> = (0..polygon.len()).collect();
+    let mut triangles = Vec::with_capacity(polygon.len() - 2);
+    while remaining.len() > 3 {
+        let count = remaining.len();
+        let corners = |i: usize| (remaining[(i + count - 1) % count], remaining[i], remaining[(i + 1) % count]);
+        let is_ear = |i: usize| {
+            let (prev, current, next) = corners(i);
+            let (a, b, c) = (positions[prev], positions[current], positions[next]);
+            turns_left(a, b, c) && !remaining.iter()
+                .filter(|&&other| other != prev && other != current && other != next)
+                .any(|&other| {
+                    let p = positions[other];
+                    !turns_left(b, a, p) && !turns_left(c, b, p) && !turns_left(a, c, p)
+                })
+        };
+
+        // Self-intersecting or degenerate polygons can run out of ears, and
+        // then get fanned like convex ones
+        let ear = (1..=count).map(|i| i % count).find(|&i| is_ear(i)).unwrap_or(1);
+        let (prev, current, next) = corners(ear);
+        triangles.push([polygon[prev], polygon[current], polygon[next]]);
+        remaining.remove(ear);
+    }
+    triangles.push([polygon[remaining[0]], polygon[remaining[1]], polygon[remaining[2]]]);
+    triangles
+}
+
+/// Reads the numbers following the keyword in `values` into a `Vec3`.
+/// At least `required` have to be present, missing ones default to 0.
+pub(crate) fn parse_vec3(values: &[&str], required: usize) -> Result<Vec3, ParseErrorKind> {
+    let found = values.len() - 1;
+    if found < required {
+        return Err(ParseErrorKind::MissingComponents { expected: required, found });
+    }
+
+    let mut components = [0.0; 3];
+    for (component, value) in components.iter_mut().zip(values.iter().skip(1)) {
+        *component = value.parse::<f32>()
+            .map_err(|_| ParseErrorKind::InvalidNumber(value.to_string()))?;
+    }
+    Ok(Vec3::from_array(components))
+}
+
+/// Parses one of `v`, `v/vt`, `v//vn` or `v/vt/vn`.
+fn parse_face_vertex(value: &str, vert_count: usize, tex_count: usize, norm_count: usize) -> Result<FaceVertex, ParseErrorKind> {
+    let face_values: Vec<&str> = value.split('/').collect();
+    if face_values.len() > 3 || face_values[0].is_empty() {
+        return Err(ParseErrorKind::InvalidFaceVertex(value.to_string()));
+    }
+
+    let optional_index = |i: usize, count: usize| match face_values.get(i) {
+        None | Some(&"") => Ok(None),
+        Some(index) => parse_index(index, count).map(Some),
+    };
+
+    Ok(FaceVertex {
+        vert: parse_index(face_values[0], vert_count)?,
+        tex_coord: optional_index(1, tex_count)?,
+        normal: optional_index(2, norm_count)?,
+    })
+}
+
+/// Turns a 1-based OBJ index into a 0-based one, checking it refers to one
+/// of the `count` elements read so far. Negative indices count back from
+/// the most recent element.
+fn parse_index(value: &str, count: usize) -> Result<usize, ParseErrorKind> {
+    let index = value.parse::<i64>()
+        .map_err(|_| ParseErrorKind::InvalidIndex(value.to_string()))?;
+    let resolved = match index {
+        i if i > 0 => i - 1,
+        i if i < 0 => count as i64 + i,
+        _ => -1,
+    };
+    if resolved < 0 || resolved as usize >= count {
+        return Err(ParseErrorKind::IndexOutOfRange { index, count });
+    }
+    Ok(resolved as usize)
+}
+
+#[derive(Debug)]
+pub enum ObjError {
+    UnsupportedExtension { path: PathBuf },
+    Io { path: PathBuf, source: std::io::Error },
+    Parse { path: PathBuf, line: usize, kind: ParseErrorKind },
+    /// A texture map of a material library failed to load
+    Texture { path: PathBuf, line: usize, source: TextureError },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseErrorKind {
+    InvalidNumber(String),
+    InvalidIndex(String),
+    MissingComponents { expected: usize, found: usize },
+    IndexOutOfRange { index: i64, count: usize },
+    DegenerateFace(usize),
+    InvalidFaceVertex(String),
+    /// A material statement before any `newmtl`
+    NoCurrentMaterial(String),
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ObjError::UnsupportedExtension { path } => write!(f, "{}: not an .obj file", path.display()),
+            ObjError::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            ObjError::Parse { path, line, kind } => write!(f, "{}:{}: {}", path.display(), line, kind),
+            ObjError::Texture { path, line, source } => write!(f, "{}:{}: {}", path.display(), line, source),
+        }
+    }
+}
+
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseErrorKind::InvalidNumber(value) => write!(f, "invalid number `{}`", value),
+            ParseErrorKind::InvalidIndex(value) => write!(f, "invalid index `{}`", value),
+            ParseErrorKind::MissingComponents { expected, found } => write!(f, "expected at least {} components, found {}", expected, found),
+            ParseErrorKind::IndexOutOfRange { index, count } => write!(f, "index {} out of range, only {} defined so far", index, count),
+            ParseErrorKind::DegenerateFace(corners) => write!(f, "face has {} vertices, needs at least 3", corners),
+            ParseErrorKind::InvalidFaceVertex(value) => write!(f, "face vertex `{}` is not one of v, v/vt, v//vn or v/vt/vn", value),
+            ParseErrorKind::NoCurrentMaterial(keyword) => write!(f, "`{}` before any `newmtl`", keyword),
+        }
+    }
+}
+
+impl std::error::Error for ObjError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ObjError::Io { source, .. } => Some(source),
+            ObjError::Texture { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(contents: &str) -> Result<Model, ObjError> {
+        Model::parse(contents, Path::new("test.obj"))
+    }
+
+    fn corners(face: &[FaceVertex; 3]) -> [usize; 3] {
+        face.map(|corner| corner.vert)
+    }
+
+    /// Twice the area of the triangle, positive if it winds counterclockwise
+    /// seen from +Z.
+    fn signed_area(model: &Model, face: &[FaceVertex; 3]) -> f32 {
+        let [a, b, c] = face.map(|corner| model.verts[corner.vert]);
+        (b - a).cross(c - a).z
+    }
+
+    #[test]
+    fn vertex_only_faces() {
+        let model = parse("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n").unwrap();
+        assert_eq!(model.verts[1], Vec3::X);
+        assert_eq!(model.faces.len(), 1);
+        assert_eq!(model.faces[0][2], FaceVertex { vert: 2, tex_coord: None, normal: None });
+    }
+
+    #[test]
+    fn vertex_and_normal_faces() {
+        let model = parse("v 0 0 0\nv 1 0 0\nv 0 1 0\nvn 0 0 1\nf 1//1 2//1 3//1\n").unwrap();
+        assert_eq!(model.normals, vec![Vec3::Z]);
+        assert!(model.faces[0].iter().all(|corner| corner.tex_coord.is_none() && corner.normal == Some(0)));
+    }
+
+    #[test]
+    fn negative_indices_count_back() {
+        let contents = "v 9 9 9\nv 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0 0\nvt 1 0\nvt 0 1\nf -3/-3 -2/-2 -1/-1\n";
+        let model = parse(contents).unwrap();
+        assert_eq!(corners(&model.faces[0]), [1, 2, 3]);
+        assert_eq!(model.faces[0].map(|corner| corner.tex_coord), [Some(0), Some(1), Some(2)]);
+    }
+
+    #[test]
+    fn two_component_tex_coords() {
+        let model = parse("vt 0.5 0.25\nvt 1\n").unwrap();
+        assert_eq!(model.tex_coords, vec![Vec3::new(0.5, 0.25, 0.0), Vec3::new(1.0, 0.0, 0.0)]);
+    }
 
-        Self { verts, tex_coords, normals, faces }
+    #[test]
+    fn index_out_of_range() {
+        let Err(ObjError::Parse { line, kind, .. }) = parse("v 0 0 0\nv 1 0 0\n\nf 1 2 3\n") else {
+            panic!("face with a missing vertex parsed");
+        };
+        assert_eq!(line, 4);
+        assert_eq!(kind, ParseErrorKind::IndexOutOfRange { index: 3, count: 2 });
+    }
+
+    #[test]
+    fn convex_polygons_are_fanned() {
+        let model = parse("v 0 0 0\nv 1 0 0\nv 2 1 0\nv 1 2 0\nv 0 1 0\nf 1 2 3 4 5\n").unwrap();
+        let faces: Vec<[usize; 3]> = model.faces.iter().map(corners).collect();
+        assert_eq!(faces, vec![[0, 1, 2], [0, 2, 3], [0, 3, 4]]);
+    }
+
+    #[test]
+    fn concave_polygons_stay_inside() {
+        // An arrowhead and an L, both starting where a fan around the first
+        // corner would reach across the notch
+        let contents = "v 0 0 0\nv 2 0 0\nv 1 3 0\nv 1 1 0\nf 1 2 3 4\n\
+                        v 3 1 0\nv 1 1 0\nv 1 3 0\nv 0 3 0\nv 0 0 0\nv 3 0 0\nf 5 6 7 8 9 10\n";
+        let model = parse(contents).unwrap();
+        let arrowhead = &model.faces[..2];
+        let l_shape = &model.faces[2..];
+        assert_eq!(l_shape.len(), 4);
+
+        for (faces, area) in [(arrowhead, 2.0), (l_shape, 5.0)] {
+            assert!(faces.iter().all(|face| signed_area(&model, face) > 0.0), "triangle flipped: {:?}", faces);
+            let total: f32 = faces.iter().map(|face| signed_area(&model, face) / 2.0).sum();
+            assert_eq!(total, area);
+        }
     }
 }
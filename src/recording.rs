@@ -0,0 +1,198 @@
+use std::{fmt, path::{Path, PathBuf}};
+
+use glam::Vec2;
+use serde::{Deserialize, Serialize};
+
+use crate::command::{InputManager, Key, MouseButton};
+
+/// A single change in input, as fed to `InputManager::handle_event`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum InputEvent {
+    Key { key: Key, pressed: bool },
+    MouseButton { button: MouseButton, pressed: bool },
+    /// Window pixels, y pointing down
+    CursorMoved(Vec2),
+    /// Raw mouse movement in pixels
+    MouseMotion(Vec2),
+    /// Lines scrolled
+    Scroll(Vec2),
+}
+
+/// An event along with the frame it arrived in, counting from the start
+/// of the recording.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RecordedEvent {
+    pub frame: u64,
+    pub event: InputEvent,
+}
+
+/// Input captured by `InputManager::start_recording`. Replaying it with
+/// the same fixed time step drives the same updates frame by frame.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct InputRecording {
+    /// Frames that were ended while recording, including ones without input
+    pub frame_count: u64,
+    /// In the order they arrived
+    pub events: Vec<RecordedEvent>,
+}
+
+impl InputRecording {
+    pub fn load<P: AsRef<Path>>(filename: P) -> Result<Self, RecordingError> {
+        let path = filename.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .map_err(|source| RecordingError::Io { path: path.to_path_buf(), source })?;
+        ron::from_str(&contents)
+            .map_err(|source| RecordingError::Parse { path: path.to_path_buf(), source: Box::new(source) })
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, filename: P) -> Result<(), RecordingError> {
+        let path = filename.as_ref();
+        let contents = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|source| RecordingError::Serialize { path: path.to_path_buf(), source })?;
+        std::fs::write(path, contents)
+            .map_err(|source| RecordingError::Io { path: path.to_path_buf(), source })
+    }
+}
+
+/// Plays a recording back into an `InputManager`, one frame at a time.
+pub struct InputReplay {
+    recording: InputRecording,
+    frame: u64,
+    next_event: usize,
+}
+
+impl InputReplay {
+    pub fn new(recording: InputRecording) -> Self {
+        Self {
+            recording,
+            frame: 0,
+            next_event: 0,
+        }
+    }
+
+    /// Feeds the events of the next frame to `input_manager`. Call it at
+    /// the start of every frame, before updating, and call `end_frame` on
+    /// the input manager afterwards as usual. Returns `false` once every
+    /// recorded frame has been played.
+    pub fn play_frame(&mut self, input_manager: &mut InputManager) -> bool {
+        if self.is_finished() {
+            return false;
+        }
+
+        while let Some(recorded) = self.recording.events.get(self.next_event) {
+            if recorded.frame != self.frame {
+                break;
+            }
+            input_manager.handle_event(recorded.event);
+            self.next_event += 1;
+        }
+
+        self.frame += 1;
+        true
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.frame >= self.recording.frame_count
+    }
+
+    /// Number of frames played so far.
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+}
+
+#[derive(Debug)]
+pub enum RecordingError {
+    Io { path: PathBuf, source: std::io::Error },
+    Parse { path: PathBuf, source: Box<ron::error::SpannedError> },
+    Serialize { path: PathBuf, source: ron::Error },
+}
+
+impl fmt::Display for RecordingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecordingError::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            RecordingError::Parse { path, source } => write!(f, "{}:{}", path.display(), source),
+            RecordingError::Serialize { path, source } => write!(f, "{}: {}", path.display(), source),
+        }
+    }
+}
+
+impl std::error::Error for RecordingError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RecordingError::Io { source, .. } => Some(source),
+            RecordingError::Parse { source, .. } => Some(source.as_ref()),
+            RecordingError::Serialize { source, .. } => Some(source),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// What behaviors can see of the input after a frame's events.
+    #[derive(Debug, PartialEq)]
+    struct FrameState {
+        forward: f32,
+        look_x: f32,
+        jumped: bool,
+        orbiting: bool,
+        released_orbit: bool,
+    }
+
+    fn frame_state(input: &InputManager) -> FrameState {
+        FrameState {
+            forward: input.axis("move_forward"),
+            look_x: input.axis("look_x"),
+            jumped: input.is_key_just_pressed(Key::Space),
+            orbiting: input.is_action_pressed("orbit"),
+            released_orbit: input.is_action_just_released("orbit"),
+        }
+    }
+
+    #[test]
+    fn replay_matches_the_recording() {
+        let frames: Vec<Vec<InputEvent>> = vec![
+            vec![InputEvent::Key { key: Key::W, pressed: true }, InputEvent::MouseMotion(Vec2::new(4.0, 0.0))],
+            vec![],
+            vec![InputEvent::Key { key: Key::Space, pressed: true },
+                 InputEvent::MouseButton { button: MouseButton::Left, pressed: true }],
+            vec![InputEvent::Key { key: Key::Space, pressed: true }, InputEvent::MouseMotion(Vec2::new(-1.5, 2.0))],
+            vec![InputEvent::MouseButton { button: MouseButton::Left, pressed: false },
+                 InputEvent::Key { key: Key::W, pressed: false }],
+            vec![],
+        ];
+
+        let mut input = InputManager::new();
+        input.start_recording();
+        let mut recorded_states = Vec::new();
+        for events in &frames {
+            for &event in events {
+                input.handle_event(event);
+            }
+            recorded_states.push(frame_state(&input));
+            input.end_frame();
+        }
+        let recording = input.stop_recording().unwrap();
+        assert_eq!(recording.frame_count, frames.len() as u64);
+
+        let path = std::env::temp_dir().join(format!("rs_sloth_renderer_{}_recording.ron", std::process::id()));
+        recording.save(&path).unwrap();
+        let loaded = InputRecording::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        let loaded = loaded.unwrap();
+        assert_eq!(loaded, recording);
+
+        let mut input = InputManager::new();
+        let mut replay = InputReplay::new(loaded);
+        let mut replayed_states = Vec::new();
+        while replay.play_frame(&mut input) {
+            replayed_states.push(frame_state(&input));
+            input.end_frame();
+        }
+        assert_eq!(replay.frame(), frames.len() as u64);
+        assert_eq!(replayed_states, recorded_states);
+    }
+}
@@ -1,8 +1,12 @@
 use std::mem::size_of;
 
-use crate::{model, texture, scene, camera};
+use crate::{model, texture, material, scene, camera, clip::{self, ClipVertex}, light::SceneLight, shader::{Shader, Varyings, VertexInput, Fragment, Uniforms, Surface, ShadingMode, VertexLitShader, PhongShader}};
 use glam::{IVec2, Vec2, Vec3, Vec4, Mat4};
+use rayon::prelude::*;
 
+/// Side length in pixels of the square tiles triangles get binned into
+/// when shading on more than one thread.
+pub const TILE_SIZE: usize = 64;
 
 #[derive(Debug)]
 pub struct Renderer {
@@ -10,6 +14,124 @@ pub struct Renderer {
     height: usize,
     pixels: Vec<u8>,
     z_buffer: Vec<f32>,
+    thread_pool: Option<rayon::ThreadPool>,
+}
+
+/// A triangle that survived clipping and culling, in screen space. Each
+/// point carries 1/w in its `w` component.
+#[derive(Debug, Clone, Copy)]
+struct ScreenTriangle<V> {
+    points: [Vec4; 3],
+    varyings: [V; 3],
+}
+
+impl<V> ScreenTriangle<V> {
+    /// Inclusive pixel bounds of the triangle within `min` and `max`, or
+    /// `None` if it doesn't touch that area.
+    fn bounds(&self, min: IVec2, max: IVec2) -> Option<(IVec2, IVec2)> {
+        let mut bbox_min = max;
+        let mut bbox_max = min;
+
+        for v in &self.points {
+            bbox_min.x = bbox_min.x.min(v.x as i32);
+            bbox_min.y = bbox_min.y.min(v.y as i32);
+            bbox_max.x = bbox_max.x.max(v.x as i32);
+            bbox_max.y = bbox_max.y.max(v.y as i32);
+        }
+
+        // Clipping
+        let bbox_min = bbox_min.max(min);
+        let bbox_max = bbox_max.min(max);
+
+        (bbox_min.x <= bbox_max.x && bbox_min.y <= bbox_max.y).then_some((bbox_min, bbox_max))
+    }
+}
+
+/// A rectangle of the color and depth buffers, or of copies of them. The
+/// slices begin at pixel `origin`, so every tile can be shaded on its own
+/// copy in parallel.
+struct Tile<'a> {
+    pixels: &'a mut [u8],
+    z_buffer: &'a mut [f32],
+    stride: usize,
+    origin: IVec2,
+    min: IVec2,
+    max: IVec2,
+}
+
+impl Tile<'_> {
+    fn rasterize<S: Shader>(&mut self, shader: &S, triangle: &ScreenTriangle<S::Varyings>) {
+        let Some((bbox_min, bbox_max)) = triangle.bounds(self.min, self.max) else {
+            return;
+        };
+        let [p0, p1, p2] = triangle.points;
+        let [v0, v1, v2] = triangle.varyings;
+        // Also used off the triangle for derivatives, where the weights
+        // simply extrapolate
+        let varyings_at = |x: i32, y: i32| {
+            let (w0, w1, w2) = Renderer::barycentric(p0.truncate(), p1.truncate(), p2.truncate(), Vec3::new(x as f32, y as f32, 0.0));
+            let (w0, w1, w2) = Renderer::perspective_correct(p0, p1, p2, w0, w1, w2);
+            S::Varyings::interpolate(v0, v1, v2, w0, w1, w2)
+        };
+
+        // Pixels are shaded in 2x2 quads starting at even coordinates, which
+        // never straddle tiles. The quad's four interpolations also give its
+        // derivatives, instead of two more interpolations per fragment.
+        for quad_y in (bbox_min.y & !1..=bbox_max.y).step_by(2) {
+            for quad_x in (bbox_min.x & !1..=bbox_max.x).step_by(2) {
+                let corner = |i: usize| (quad_x + (i & 1) as i32, quad_y + (i >> 1) as i32);
+
+                // Buffer index and depth of the pixels to shade
+                let mut covered = [None; 4];
+                for (i, covered) in covered.iter_mut().enumerate() {
+                    let (x, y) = corner(i);
+                    if x < bbox_min.x || x > bbox_max.x || y < bbox_min.y || y > bbox_max.y {
+                        continue;
+                    }
+                    let (w0, w1, w2) = Renderer::barycentric(p0.truncate(), p1.truncate(), p2.truncate(), Vec3::new(x as f32, y as f32, 0.0));
+
+                    let is_inside = w0 >= 0.0 && w1 >= 0.0 && w2 >= 0.0;
+
+                    if is_inside {
+                        let z_interpolated = w0 * p0.z + w1 * p1.z + w2 * p2.z;
+                        let index = (x - self.origin.x) as usize + (y - self.origin.y) as usize * self.stride;
+                        if z_interpolated < self.z_buffer[index] {
+                            *covered = Some((index, z_interpolated));
+                        }
+                    }
+                }
+                if covered.iter().all(Option::is_none) {
+                    continue;
+                }
+
+                let varyings = [0, 1, 2, 3].map(|i| {
+                    let (x, y) = corner(i);
+                    varyings_at(x, y)
+                });
+                let ddx = varyings[1].add(varyings[0].scale(-1.0));
+                let ddy = varyings[2].add(varyings[0].scale(-1.0));
+
+                for (i, covered) in covered.into_iter().enumerate() {
+                    let Some((index, z_interpolated)) = covered else {
+                        continue;
+                    };
+                    let (x, y) = corner(i);
+                    let fragment = Fragment {
+                        position: Vec3::new(x as f32, y as f32, z_interpolated),
+                        varyings: varyings[i],
+                        ddx,
+                        ddy,
+                    };
+
+                    // Discarded fragments leave the depth buffer alone
+                    if let Some(color) = shader.fragment(&fragment) {
+                        self.z_buffer[index] = z_interpolated;
+                        self.pixels[index * 4..index * 4 + 4].copy_from_slice(&[color.r, color.g, color.b, color.a]);
+                    }
+                }
+            }
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -19,10 +141,10 @@ pub enum Error {
 
 #[derive(Debug, Clone, Copy)]
 pub struct Color {
-    r: u8,
-    g: u8,
-    b: u8,
-    a: u8,
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
 }
 
 impl Color {
@@ -37,6 +159,24 @@ impl Color {
         let a = (self.a as f32 * intensity) as u8;
         Self { r, g, b, a }
     }
+
+    /// RGB channels in the 0..=1 range.
+    pub fn to_vec3(self) -> Vec3 {
+        Vec3::new(self.r as f32, self.g as f32, self.b as f32) / 255.0
+    }
+
+    /// Builds a color from RGB channels in the 0..=1 range, clamping
+    /// anything outside of it.
+    pub fn from_vec3(rgb: Vec3, a: u8) -> Self {
+        let rgb = (rgb.clamp(Vec3::ZERO, Vec3::ONE) * 255.0).round();
+        Self::new(rgb.x as u8, rgb.y as u8, rgb.z as u8, a)
+    }
+}
+
+impl From<Color> for Vec4 {
+    fn from(color: Color) -> Self {
+        Vec4::new(color.r as f32, color.g as f32, color.b as f32, color.a as f32)
+    }
 }
 
 impl Renderer {
@@ -44,13 +184,31 @@ impl Renderer {
         Self {
             width,
             height,
-            pixels: vec![0; (width * height * size_of::<Color>()) as usize],
-            z_buffer: vec![std::f32::MAX; (width * height) as usize],
+            pixels: vec![0; width * height * size_of::<Color>()],
+            z_buffer: vec![f32::MAX; width * height],
+            thread_pool: None,
         }
     }
 
+    /// Shades triangles on `threads` threads, tile by tile. With
+    /// a single thread everything runs on the caller's thread instead, and
+    /// 0 starts one thread per core. Both paths produce the same image.
+    pub fn set_thread_count(&mut self, threads: usize) {
+        self.thread_pool = match threads {
+            1 => None,
+            _ => Some(rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .expect("Failed to start render threads")),
+        };
+    }
+
+    pub fn thread_count(&self) -> usize {
+        self.thread_pool.as_ref().map_or(1, |pool| pool.current_num_threads())
+    }
+
     pub fn set_pixel(&mut self, index: usize, color: Color) -> Result<(), Error> {
-        if index >= self.width as usize * self.height as usize {
+        if index >= self.width * self.height {
             return Err(Error::OutOfBounds);
         }
         self.pixels[index * 4] = color.r;
@@ -61,7 +219,7 @@ impl Renderer {
     }
 
     pub fn get_pixel_color(&self, index: usize) -> Result<Color, Error> {
-        if index >= self.width as usize * self.height as usize {
+        if index >= self.width * self.height {
             return Err(Error::OutOfBounds);
         }
 
@@ -76,7 +234,7 @@ impl Renderer {
     pub fn clear(&mut self, color: Color) {
         for i in 0..self.width {
             for j in 0..self.height {
-                let index = (i + j * self.width) as usize;
+                let index = i + j * self.width;
                 self.set_pixel(index, color).unwrap();
             }
         }
@@ -105,29 +263,15 @@ impl Renderer {
 
         let mut steep = false;
         if (x0 - x1).abs() < (y0 - y1).abs() {
-            // swap x0, y0
-            let temp = x0;
-            x0 = y0;
-            y0 = temp;
-
-            // swap x1, y1
-            let temp = x1;
-            x1 = y1;
-            y1 = temp;
+            std::mem::swap(&mut x0, &mut y0);
+            std::mem::swap(&mut x1, &mut y1);
 
             steep = true;
         }
 
         if x0 > x1 {
-            // swap x0, x1
-            let temp = x0;
-            x0 = x1;
-            x1 = temp;
-
-            // swap y0, y1
-            let temp = y0;
-            y0 = y1;
-            y1 = temp;
+            std::mem::swap(&mut x0, &mut x1);
+            std::mem::swap(&mut y0, &mut y1);
         }
 
         let dx = x1 - x0;
@@ -153,167 +297,278 @@ impl Renderer {
         }
     }
 
-    pub fn triangle2d(&mut self, p0: Vec3, p1: Vec3, p2: Vec3, uv0: Vec2, uv1: Vec2, uv2: Vec2, texture: &texture::Texture, intensity: f32) {
-        let mut bbox_min = IVec2::new(self.width as i32 - 1, self.height as i32 - 1);
-        let mut bbox_max = IVec2::new(0, 0);
+    #[allow(clippy::too_many_arguments)]
+    pub fn triangle2d<S: Shader>(&mut self, shader: &S, p0: Vec4, p1: Vec4, p2: Vec4, v0: S::Varyings, v1: S::Varyings, v2: S::Varyings) {
+        let triangle = ScreenTriangle {
+            points: [p0, p1, p2],
+            varyings: [v0, v1, v2],
+        };
+        self.full_screen_tile().rasterize(shader, &triangle);
+    }
 
-        for v in &[p0, p1, p2] {
-            bbox_min.x = bbox_min.x.min(v.x as i32);
-            bbox_min.y = bbox_min.y.min(v.y as i32);
-            bbox_max.x = bbox_max.x.max(v.x as i32);
-            bbox_max.y = bbox_max.y.max(v.y as i32);
+    fn full_screen_tile(&mut self) -> Tile<'_> {
+        Tile {
+            pixels: &mut self.pixels,
+            z_buffer: &mut self.z_buffer,
+            stride: self.width,
+            origin: IVec2::ZERO,
+            min: IVec2::ZERO,
+            max: IVec2::new(self.width as i32 - 1, self.height as i32 - 1),
         }
+    }
 
-        // Clipping
-        bbox_min.x = bbox_min.x.max(0);
-        bbox_min.y = bbox_min.y.max(0);
-        bbox_max.x = bbox_max.x.min(self.width as i32 - 1);
-        bbox_max.y = bbox_max.y.min(self.height as i32 - 1);
-
-
-        /*
-        self.line((bbox_min.x, bbox_min.y), (bbox_max.x, bbox_min.y), Color::new(255, 0, 0, 255));
-        self.line((bbox_min.x, bbox_max.y), (bbox_max.x, bbox_max.y), Color::new(255, 0, 0, 255));
-        self.line((bbox_min.x, bbox_min.y), (bbox_min.x, bbox_max.y), Color::new(255, 0, 0, 255));
-        self.line((bbox_max.x, bbox_min.y), (bbox_max.x, bbox_max.y), Color::new(255, 0, 0, 255));
-        */
-        
-        for x in bbox_min.x..=bbox_max.x {
-            for y in bbox_min.y..=bbox_max.y {
-                if y == self.height as i32 || x == self.width as i32{
-                    continue;
+    /// Rasterizes `triangles` in order, either serially or binned into
+    /// tiles that are shaded in parallel.
+    fn rasterize_triangles<S: Shader>(&mut self, shader: &S, triangles: &[ScreenTriangle<S::Varyings>]) {
+        let Some(thread_pool) = &self.thread_pool else {
+            let mut tile = self.full_screen_tile();
+            for triangle in triangles {
+                tile.rasterize(shader, triangle);
+            }
+            return;
+        };
+
+        let (width, height) = (self.width, self.height);
+        let tiles_x = (width + TILE_SIZE - 1) / TILE_SIZE;
+        let tiles_y = (height + TILE_SIZE - 1) / TILE_SIZE;
+        let screen_max = IVec2::new(width as i32 - 1, height as i32 - 1);
+
+        // Keeping submission order within each bin makes every pixel see the
+        // same sequence of depth tests as the serial path
+        let mut bins: Vec<Vec<usize>> = vec![Vec::new(); tiles_x * tiles_y];
+        for (i, triangle) in triangles.iter().enumerate() {
+            if let Some((min, max)) = triangle.bounds(IVec2::ZERO, screen_max) {
+                for ty in min.y as usize / TILE_SIZE..=max.y as usize / TILE_SIZE {
+                    for tx in min.x as usize / TILE_SIZE..=max.x as usize / TILE_SIZE {
+                        bins[ty * tiles_x + tx].push(i);
+                    }
                 }
-                let pos = Vec3::new(x as f32, y as f32, 0.0);
-                let (w1, w2, w3) = self.barycentric(p0, p1, p2, pos);
+            }
+        }
 
-                let is_inside = w1 >= 0.0 && w2 >= 0.0 && w3 >= 0.0;
+        // Each tile is shaded on a copy of its part of the buffers, which is
+        // copied back once all tiles are done
+        let pixels = &self.pixels;
+        let z_buffer = &self.z_buffer;
+        let shaded: Vec<_> = thread_pool.install(|| {
+            bins.par_iter().enumerate().filter(|(_, bin)| !bin.is_empty()).map(|(t, bin)| {
+                let min = IVec2::new((t % tiles_x * TILE_SIZE) as i32, (t / tiles_x * TILE_SIZE) as i32);
+                let max = (min + TILE_SIZE as i32 - 1).min(screen_max);
+                let tile_width = (max.x - min.x + 1) as usize;
+
+                let mut tile_pixels = Vec::with_capacity(TILE_SIZE * TILE_SIZE * size_of::<Color>());
+                let mut tile_z_buffer = Vec::with_capacity(TILE_SIZE * TILE_SIZE);
+                for y in min.y as usize..=max.y as usize {
+                    let start = y * width + min.x as usize;
+                    tile_pixels.extend_from_slice(&pixels[start * 4..(start + tile_width) * 4]);
+                    tile_z_buffer.extend_from_slice(&z_buffer[start..start + tile_width]);
+                }
 
-                if is_inside {
-                    let z_interpolated = w1 * p0.z + w2 * p1.z + w3 * p2.z;
-                    let index = (x + (y * self.width as i32)) as usize;
-                    if self.z_buffer[index] < z_interpolated {
-                        self.z_buffer[index] = z_interpolated;
-                        let uv_interpolated = self.interpolate_uv(uv0, uv1, uv2, w1, w2, w3);
-                        let mut color = texture.sample(uv_interpolated);
-                        color = color.scale_by_intensity(intensity);
-                        self.set_pixel(index, color).unwrap();
-                    }
+                let mut tile = Tile {
+                    pixels: &mut tile_pixels,
+                    z_buffer: &mut tile_z_buffer,
+                    stride: tile_width,
+                    origin: min,
+                    min,
+                    max,
+                };
+                for &i in bin {
+                    tile.rasterize(shader, &triangles[i]);
                 }
+                (min, tile_width, tile_pixels, tile_z_buffer)
+            }).collect()
+        });
+
+        for (min, tile_width, tile_pixels, tile_z_buffer) in shaded {
+            let rows = tile_pixels.chunks(tile_width * 4).zip(tile_z_buffer.chunks(tile_width));
+            for (y, (row_pixels, row_depths)) in (min.y as usize..).zip(rows) {
+                let start = y * width + min.x as usize;
+                self.pixels[start * 4..(start + tile_width) * 4].copy_from_slice(row_pixels);
+                self.z_buffer[start..start + tile_width].copy_from_slice(row_depths);
             }
         }
     }
 
-    fn interpolate_uv(&self, uv0: Vec2, uv1: Vec2, uv2: Vec2, w1: f32, w2: f32, w3: f32) -> Vec2 {
-        let u = w1 * uv0.x + w2 * uv1.x + w3 * uv2.x;
-        let v = w1 * uv0.y + w2 * uv1.y + w3 * uv2.y;
-        Vec2::new(u, v)
+    /// Turns screen-space barycentric weights into ones that interpolate
+    /// attributes linearly in view space. Each vertex carries 1/w in its
+    /// `w` component; any varying can be interpolated with the result.
+    fn perspective_correct(p0: Vec4, p1: Vec4, p2: Vec4, w0: f32, w1: f32, w2: f32) -> (f32, f32, f32) {
+        let c0 = w0 * p0.w;
+        let c1 = w1 * p1.w;
+        let c2 = w2 * p2.w;
+        let sum = c0 + c1 + c2;
+        (c0 / sum, c1 / sum, c2 / sum)
     }
 
-    fn barycentric(&mut self, p0: Vec3, p1: Vec3, p2: Vec3, p: Vec3) -> (f32, f32, f32) {
-        let w1 = (p0.x * (p2.y - p0.y) + (p.y - p0.y) * (p2.x - p0.x) - p.x * (p2.y - p0.y)) / ((p1.y - p0.y) * (p2.x - p0.x) - (p1.x - p0.x) * (p2.y - p0.y));
-        let w2 = (p.y - p0.y - w1 * (p1.y - p0.y))  / (p2.y - p0.y);
-        let w3 = 1.0 - w1 - w2;
-        (w1, w2, w3)
+    /// Returns the barycentric weights of `p` with respect to `p0`, `p1`
+    /// and `p2`, in that order. Degenerate triangles produce non-finite
+    /// weights, which never pass the inside test.
+    fn barycentric(p0: Vec3, p1: Vec3, p2: Vec3, p: Vec3) -> (f32, f32, f32) {
+        let area = (p1.x - p0.x) * (p2.y - p0.y) - (p2.x - p0.x) * (p1.y - p0.y);
+        let w1 = ((p.x - p0.x) * (p2.y - p0.y) - (p2.x - p0.x) * (p.y - p0.y)) / area;
+        let w2 = ((p1.x - p0.x) * (p.y - p0.y) - (p.x - p0.x) * (p1.y - p0.y)) / area;
+        let w0 = 1.0 - w1 - w2;
+        (w0, w1, w2)
     }
 
+    /// Depth runs from 0 at the near plane to 1 at the far plane, so the
+    /// buffer starts out at the far end and closer fragments win.
     pub fn reset_z_buffer(&mut self) {
-        for i in 0..self.z_buffer.len() {
-            self.z_buffer[i] = std::f32::MIN;
-        }
+        self.z_buffer.fill(f32::MAX);
     }
 
-    pub fn draw_model(&mut self, model: &model::Model, texture: &texture::Texture, model_matrix: Mat4, view_matrix: Mat4, projection_matrix: Mat4, _: Color) {
-        let light_dir = Vec3::new(0.0, 0.0, -1.0); // This should come from scene
-
-        for (_, face) in model.faces.iter().enumerate() {
-            let mut screen_coords: Vec<Vec3> = Vec::new(); // This should come from Camera
-            let mut world_coords: Vec<Vec3> = Vec::new();  // This should come from Scene
-            let mut texture_coords: Vec<Vec2> = Vec::new(); // This should come from Model
-
-            for (v_idx, vt_idx, vn_idx) in face.iter() {
-                let v = model.verts.get(*v_idx as usize).unwrap();
-                let vt = model.tex_coords.get(*vt_idx as usize).unwrap();
-                let _vn = model.normals.get(*vn_idx as usize).unwrap();
+    pub fn draw_model<S: Shader>(&mut self, model: &model::Model, shader: &S) {
+        self.draw_faces(model, 0..model.faces.len(), shader);
+    }
 
-                screen_coords.push(self.transform_vertex(v.clone(), model_matrix, view_matrix, projection_matrix));
-                texture_coords.push(Vec2::new(vt.x, (1.0 - vt.y).abs()));
-                world_coords.push(Vec3::new(v.x, v.y, v.z));
+    /// Draws a run of the model's faces, such as one of its submeshes.
+    pub fn draw_faces<S: Shader>(&mut self, model: &model::Model, faces: std::ops::Range<usize>, shader: &S) {
+        let mut triangles = Vec::new();
+
+        for face in model.faces[faces].iter() {
+            let mut corners: Vec<VertexInput> = Vec::new();
+
+            let positions = face.map(|corner| model.verts[corner.vert]);
+            let face_normal = (positions[1] - positions[0])
+                .cross(positions[2] - positions[0])
+                .normalize_or_zero();
+
+            for (corner, position) in face.iter().zip(positions) {
+                let tex_coord = corner.tex_coord
+                    .map_or(Vec2::ZERO, |vt| {
+                        let vt = model.tex_coords[vt];
+                        Vec2::new(vt.x, 1.0 - vt.y)
+                    });
+
+                corners.push(VertexInput {
+                    position,
+                    tex_coord,
+                    // Faces without normals are shaded flat
+                    normal: corner.normal.map_or(face_normal, |vn| model.normals[vn]),
+                    face_normal,
+                });
             }
 
-            let n = (world_coords[2] - world_coords[0]).cross(world_coords[1] - world_coords[0]).normalize();
-            let intensity = n.dot(light_dir);
+            let clip_coords: Vec<ClipVertex<S::Varyings>> = corners.iter()
+                .map(|corner| {
+                    let (position, varyings) = shader.vertex(corner);
+                    ClipVertex::new(position, varyings)
+                })
+                .collect();
 
-            if intensity > 0.0 {
-                self.triangle2d(screen_coords[0],
-                                screen_coords[1],
-                                screen_coords[2],
-                                // Idk why the texture coords are in this order
-                                texture_coords[1],
-                                texture_coords[2],
-                                texture_coords[0],
-                                &texture,
-                                intensity);
+            let clipped = clip::clip_polygon(&clip_coords);
+            let screen_coords: Vec<Vec4> = clipped.iter()
+                .map(|v| self.to_screen(v.position))
+                .collect();
 
+            if screen_coords.len() < 3 || !self.is_front_facing(&screen_coords) {
+                continue;
+            }
+
+            // The clipped polygon is convex, so a fan covers it
+            for i in 2..clipped.len() {
+                triangles.push(ScreenTriangle {
+                    points: [screen_coords[0], screen_coords[i - 1], screen_coords[i]],
+                    varyings: [clipped[0].varyings, clipped[i - 1].varyings, clipped[i].varyings],
+                });
             }
         }
+
+        self.rasterize_triangles(shader, &triangles);
     }
 
-    fn transform_vertex(&self, vertex: Vec3, model_matrix: Mat4, view_matrix: Mat4, projection_matrix: Mat4) -> Vec3 {
-        // Model matrix
-        let homogeneous_vertex = Vec4::new(vertex.x, vertex.y, vertex.z, 1.0);
-        let world_vertex = model_matrix * homogeneous_vertex;
-        let view_vertex = view_matrix * world_vertex;
-        let clip_space_vertex = projection_matrix * view_vertex;
-        let normalized_vertex = clip_space_vertex / clip_space_vertex.w;
+    /// Faces are wound counter-clockwise, and screen space keeps y pointing
+    /// up until the final flip, so front faces have a positive area. The
+    /// whole polygon's area counts, as clipping can leave its first three
+    /// points close to collinear.
+    fn is_front_facing(&self, polygon: &[Vec4]) -> bool {
+        let twice_area: f32 = polygon.iter()
+            .zip(polygon.iter().cycle().skip(1))
+            .map(|(a, b)| a.x * b.y - b.x * a.y)
+            .sum();
+        twice_area > 0.0
+    }
 
-        let screen_vertex = Vec3::new(
+    /// Perspective divide followed by the viewport transform. Only valid
+    /// for vertices that survived clipping, i.e. with w > 0.
+    ///
+    /// The returned `w` holds 1/w of the clip space vertex, which
+    /// `triangle2d` needs for perspective-correct interpolation.
+    fn to_screen(&self, clip_space_vertex: Vec4) -> Vec4 {
+        let inv_w = 1.0 / clip_space_vertex.w;
+        let normalized_vertex = clip_space_vertex * inv_w;
+
+        Vec4::new(
             (normalized_vertex.x + 1.0) * self.width as f32 / 2.0 + 0.5,
             (normalized_vertex.y + 1.0) * self.height as f32 / 2.0 + 0.5,
-            normalized_vertex.z
-        );
-
-        screen_vertex
+            normalized_vertex.z,
+            inv_w
+        )
     }
 
-    pub fn render_scene(&mut self,
-                        node: &scene::Node,
-                        model_manager: &model::ModelManager,
-                        texture_manager: &texture::TextureManager,
-                        camera_manager: &camera::CameraManager,
-                        pixels: &mut pixels::Pixels,
-                        is_screenshot: bool) {
+    /// Renders the scene into the renderer's own framebuffer, top row
+    /// first. Needs no window, so it works headless; read the result back
+    /// with `to_image` or `save`.
+    pub fn render(&mut self,
+                  node: &scene::Node,
+                  model_manager: &model::ModelManager,
+                  texture_manager: &texture::TextureManager,
+                  material_manager: &material::MaterialManager,
+                  camera_manager: &camera::CameraManager) {
         self.reset_z_buffer();
         self.clear(Color::new(0, 0, 0, 255));
         let root_transform = Mat4::IDENTITY;
         let camera = camera_manager.get_active_camera();
-        
+
+        // Every light has to be known before the first mesh gets shaded
+        let mut lights = Vec::new();
         node.traverse(root_transform, &mut |node, world_transform| {
-            match &node.node_type {
-                scene::NodeType::Mesh(mesh) => {
-                    match camera {
-                        Some(camera) => {
-                            let model_matrix = world_transform;
-                            let view_matrix = camera.get_view_matrix();
-                            let projection_matrix = camera.get_projection_matrix();
-                            let model = model_manager.get_model(mesh.model_id);
-                            let texture = texture_manager.get_texture(mesh.texture_id);
-                            self.draw_model(model, texture, model_matrix, view_matrix, projection_matrix, Color::new(255, 255, 255, 255));
-                        },
-                        None => {}
+            if let scene::NodeType::Light(light) = &node.node_type {
+                lights.push(SceneLight::new(light, world_transform));
+            }
+        });
+
+        node.traverse(root_transform, &mut |node, world_transform| {
+            if let scene::NodeType::Mesh(mesh) = &node.node_type {
+                if let Some(camera) = camera {
+                    let model_matrix = world_transform;
+                    let view_matrix = camera.get_view_matrix();
+                    let projection_matrix = camera.get_projection_matrix();
+                    let model = model_manager.get_model(mesh.model_id);
+                    let texture = mesh.texture_id.map(|id| texture_manager.get_texture(id));
+                    for submesh in &model.submeshes {
+                        // Submeshes without a material fall back to the mesh's texture
+                        let surface = match submesh.material {
+                            Some(id) => Surface::from_material(material_manager.get_material(id), |id| texture_manager.get_texture(id)),
+                            None => Surface::textured(texture, mesh.sampler),
+                        };
+                        let uniforms = Uniforms::new(model_matrix, view_matrix, projection_matrix, surface, &lights, camera.position);
+                        let faces = submesh.faces.clone();
+                        match mesh.shading {
+                            ShadingMode::Flat => self.draw_faces(model, faces, &VertexLitShader::flat(uniforms)),
+                            ShadingMode::Gouraud => self.draw_faces(model, faces, &VertexLitShader::gouraud(uniforms)),
+                            ShadingMode::Phong => self.draw_faces(model, faces, &PhongShader::new(uniforms)),
+                        }
                     }
-                },
-                scene::NodeType::Light(_) => {},
-                _ => {}
+                }
             }
         });
 
         self.flip_vertically();
-        if is_screenshot {
-            self.save("screenshot.png");
-        } else {
-            self.write_to_buffer(pixels.frame_mut());
-            pixels.render().unwrap();
-        }
+    }
+
+    /// Renders the scene and presents it in a window.
+    #[cfg(feature = "viewer")]
+    pub fn render_scene(&mut self,
+                        node: &scene::Node,
+                        model_manager: &model::ModelManager,
+                        texture_manager: &texture::TextureManager,
+                        material_manager: &material::MaterialManager,
+                        camera_manager: &camera::CameraManager,
+                        pixels: &mut pixels::Pixels) {
+        self.render(node, model_manager, texture_manager, material_manager, camera_manager);
+        self.write_to_buffer(pixels.frame_mut());
+        pixels.render().unwrap();
     }
 
     pub fn flip_vertically(&mut self) {
@@ -330,26 +585,29 @@ impl Renderer {
         }
     }
 
-    pub fn save(&self, filename: &str) {
-        let mut imgbuf = image::ImageBuffer::new(self.width as u32, self.height as u32);
-        for (x, y, pixel) in imgbuf.enumerate_pixels_mut() {
-            let idx = (x + y * self.width as u32) as usize;
-            match self.get_pixel_color(idx) {
-                Ok(color) => {
-                    *pixel = image::Rgba([color.r, color.g, color.b, color.a]);
-                }
-                Err(e) => {
-                    println!("Error: {:?}", e);
-                    continue;
-                }
-            }
-        }
-        imgbuf.save(filename).unwrap();
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Copies the framebuffer into an image.
+    pub fn to_image(&self) -> image::RgbaImage {
+        image::RgbaImage::from_raw(self.width as u32, self.height as u32, self.pixels.clone())
+            .expect("Framebuffer size matches its dimensions")
+    }
+
+    /// Writes the framebuffer to `filename`, in the format its extension
+    /// asks for.
+    pub fn save<P: AsRef<std::path::Path>>(&self, filename: P) -> image::ImageResult<()> {
+        self.to_image().save(filename)
     }
 
     pub fn write_to_buffer(&self, frame: &mut [u8]) {
         for (i, pixel) in frame.chunks_exact_mut(4).enumerate() {
-            match self.get_pixel_color(i as usize) {
+            match self.get_pixel_color(i) {
                 Ok(color) => {
                     pixel.copy_from_slice(&[color.r, color.g, color.b, color.a]);
                 }
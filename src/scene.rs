@@ -1,15 +1,18 @@
 use std::{sync::Arc, fmt};
 
 use glam::{Vec3, Mat4, Quat};
+use serde::{Deserialize, Serialize};
 
-use crate::{texture::TextureId, model::ModelId, camera::CameraId, command::InputManager};
+use crate::{texture::{Sampler, TextureId}, model::ModelId, camera::{CameraId, CameraManager}, command::InputManager, shader::ShadingMode, light::Light};
 
-type UpdateFn = Box<dyn Fn(&Node, &InputManager, f32)>;
+/// Runs once per frame for its node, which it may change freely, along with
+/// the cameras. Gets the seconds since the previous update.
+pub type UpdateFn = Box<dyn FnMut(&mut Node, &InputManager, &mut CameraManager, f32)>;
 
 #[derive(Debug)]
 pub enum NodeType {
     Mesh(Arc<ModelData>),
-    Light(Arc<Vec3>),
+    Light(Arc<Light>),
     Camera(Arc<CameraEntityData>),
     Group,
 }
@@ -17,7 +20,10 @@ pub enum NodeType {
 #[derive(Debug)]
 pub struct ModelData {
     pub model_id: ModelId,
-    pub texture_id: TextureId,
+    /// Diffuse texture for the submeshes that have no material
+    pub texture_id: Option<TextureId>,
+    pub sampler: Sampler,
+    pub shading: ShadingMode,
 }
 
 #[derive(Debug)]
@@ -30,7 +36,7 @@ pub struct Node {
     update_fn: UpdateFn,
     pub transformation: Transform,
     pub node_type: NodeType,
-    children: Vec<Box<Node>>,
+    children: Vec<Node>,
 }
 
 impl fmt::Debug for Node {
@@ -43,13 +49,20 @@ impl fmt::Debug for Node {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Transform {
     pub position: Vec3,
     pub rotation: Quat,
     pub scale: Vec3,
 }
 
+impl Default for Transform {
+    fn default() -> Self {
+        Self::new(Vec3::ZERO, Quat::IDENTITY, Vec3::ONE)
+    }
+}
+
 impl Transform {
     pub fn new(position: Vec3, rotation: Quat, scale: Vec3) -> Self {
         Self {
@@ -75,7 +88,7 @@ impl Node {
     }
 
     pub fn add_child(&mut self, child: Node) {
-        self.children.push(Box::new(child));
+        self.children.push(child);
     }
 
     pub fn get_transformation(&self) -> Mat4 {
@@ -86,7 +99,7 @@ impl Node {
         &self.node_type
     }
 
-    pub fn get_children(&self) -> &Vec<Box<Node>> {
+    pub fn get_children(&self) -> &Vec<Node> {
         &self.children
     }
 
@@ -101,10 +114,19 @@ impl Node {
         result
     }
 
-    fn update(&self, input_manager: &InputManager, delta_time: f32) {
-        (self.update_fn)(self, input_manager, delta_time);
-        for child in &self.children {
-            child.update(input_manager, delta_time);
+    pub fn get_children_mut(&mut self) -> &mut Vec<Node> {
+        &mut self.children
+    }
+
+    /// Updates this node, then its children.
+    pub fn update(&mut self, input_manager: &InputManager, camera_manager: &mut CameraManager, delta_time: f32) {
+        // Move the function out so it can borrow its own node mutably
+        let mut update_fn = std::mem::replace(&mut self.update_fn, Box::new(|_node, _input_manager, _camera_manager, _delta_time| {}));
+        update_fn(self, input_manager, camera_manager, delta_time);
+        self.update_fn = update_fn;
+
+        for child in &mut self.children {
+            child.update(input_manager, camera_manager, delta_time);
         }
     }
 }
@@ -0,0 +1,414 @@
+use std::{collections::HashMap, fmt, path::{Component, Path, PathBuf}, sync::Arc};
+
+use glam::Vec3;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    behaviors::standard_camera_update,
+    camera::{Camera, CameraManager},
+    gltf_loader::{self, GltfError, GltfScene},
+    light::Light,
+    material::MaterialManager,
+    model::{ModelId, ModelManager, ObjError},
+    scene::{CameraEntityData, ModelData, Node, NodeType, Transform, UpdateFn},
+    shader::ShadingMode,
+    texture::{Sampler, TextureError, TextureId, TextureManager},
+};
+
+/// A scene as written in a RON scene file: a tree of nodes referring to
+/// their assets by path, relative to the file.
+///
+/// ```ron
+/// #![enable(implicit_some)]
+/// (
+///     root: (
+///         children: [
+///             (
+///                 transform: (position: (0.0, 0.0, -5.0)),
+///                 kind: Mesh((
+///                     model: "../objs/african_head.obj",
+///                     texture: "../objs/african_head_diffuse.tga",
+///                     shading: Phong,
+///                 )),
+///             ),
+///             (
+///                 transform: (position: (2.0, 0.0, -5.0)),
+///                 kind: Mesh((model: "../models/props.glb", mesh: 2)),
+///             ),
+///             (kind: Light((kind: Directional, color: (1.0, 1.0, 1.0), intensity: 1.0))),
+///             (
+///                 transform: (position: (0.0, 0.0, 8.0)),
+///                 kind: Camera((target: (0.0, 0.0, -5.0), active: true)),
+///             ),
+///         ],
+///     ),
+/// )
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SceneFile {
+    pub root: NodeDescription,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NodeDescription {
+    pub transform: Transform,
+    pub kind: NodeKind,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<NodeDescription>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub enum NodeKind {
+    #[default]
+    Group,
+    Mesh(MeshDescription),
+    Light(Light),
+    Camera(CameraDescription),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MeshDescription {
+    /// OBJ file, loaded along with its material libraries, or glTF file
+    pub model: PathBuf,
+    /// Which mesh of a glTF file, the first if left out
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mesh: Option<usize>,
+    /// Diffuse texture for the submeshes that have no material
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub texture: Option<PathBuf>,
+    #[serde(default)]
+    pub sampler: Sampler,
+    #[serde(default)]
+    pub shading: ShadingMode,
+}
+
+/// A camera placed at its node's position. Camera nodes fly around with
+/// `behaviors::standard_camera_update`, so they belong at the top of the
+/// tree, where node and world positions agree. Cameras under a node that
+/// moves them are rejected.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CameraDescription {
+    pub target: Vec3,
+    #[serde(default = "default_up")]
+    pub up: Vec3,
+    /// Vertical field of view in degrees
+    #[serde(default = "default_fov")]
+    pub fov: f32,
+    #[serde(default = "default_aspect_ratio")]
+    pub aspect_ratio: f32,
+    #[serde(default = "default_z_near")]
+    pub z_near: f32,
+    #[serde(default = "default_z_far")]
+    pub z_far: f32,
+    /// Units per second the camera flies at
+    #[serde(default = "default_speed")]
+    pub speed: f32,
+    /// Makes this the active camera. The last one wins if several are.
+    #[serde(default)]
+    pub active: bool,
+}
+
+fn default_up() -> Vec3 { Vec3::Y }
+fn default_fov() -> f32 { 45.0 }
+fn default_aspect_ratio() -> f32 { 4.0 / 3.0 }
+fn default_z_near() -> f32 { 0.1 }
+fn default_z_far() -> f32 { 100.0 }
+fn default_speed() -> f32 { 5.0 }
+
+impl SceneFile {
+    /// Loads a scene file and everything it refers to, returning the root
+    /// of the new node tree.
+    pub fn load_scene<P: AsRef<Path>>(filename: P,
+                                      model_manager: &mut ModelManager,
+                                      texture_manager: &mut TextureManager,
+                                      material_manager: &mut MaterialManager,
+                                      camera_manager: &mut CameraManager) -> Result<Node, SceneError> {
+        let path = filename.as_ref();
+        let scene = Self::load(path)?;
+        let mut loader = Loader {
+            directory: path.parent().unwrap_or(Path::new("")),
+            model_manager,
+            texture_manager,
+            material_manager,
+            camera_manager,
+            models: HashMap::new(),
+            gltf_files: HashMap::new(),
+            textures: HashMap::new(),
+        };
+        let root = loader.instantiate(&scene.root, &[], false)?;
+        info!("Scene loaded: {} OBJ files, {} glTF files, {} textures",
+              loader.models.len(), loader.gltf_files.len(), loader.textures.len());
+        Ok(root)
+    }
+
+    /// Writes a node tree out as a scene file. Fails if a mesh's model was
+    /// not loaded from a file, since the scene file could not refer to it.
+    pub fn save_scene<P: AsRef<Path>>(filename: P,
+                                      root: &Node,
+                                      model_manager: &ModelManager,
+                                      texture_manager: &TextureManager,
+                                      camera_manager: &CameraManager) -> Result<(), SceneError> {
+        let path = filename.as_ref();
+        let directory = match path.parent() {
+            Some(directory) if directory != Path::new("") => directory,
+            _ => Path::new("."),
+        };
+        let scene = Self::from_node(root, directory, model_manager, texture_manager, camera_manager)?;
+        scene.save(path)
+    }
+
+    /// Reads the description only, without loading any assets.
+    pub fn load<P: AsRef<Path>>(filename: P) -> Result<Self, SceneError> {
+        let path = filename.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .map_err(|source| SceneError::Io { path: path.to_path_buf(), source })?;
+        ron::from_str(&contents)
+            .map_err(|source| SceneError::Parse { path: path.to_path_buf(), source: Box::new(source) })
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, filename: P) -> Result<(), SceneError> {
+        let path = filename.as_ref();
+        let config = ron::ser::PrettyConfig::default().extensions(ron::extensions::Extensions::IMPLICIT_SOME);
+        let contents = ron::ser::to_string_pretty(self, config)
+            .map_err(|source| SceneError::Serialize { path: path.to_path_buf(), source })?;
+        std::fs::write(path, contents)
+            .map_err(|source| SceneError::Io { path: path.to_path_buf(), source })
+    }
+
+    /// Describes a node tree, with asset paths made relative to
+    /// `directory` where possible.
+    pub fn from_node(root: &Node,
+                     directory: &Path,
+                     model_manager: &ModelManager,
+                     texture_manager: &TextureManager,
+                     camera_manager: &CameraManager) -> Result<Self, SceneError> {
+        Ok(Self { root: describe(root, &[], false, directory, model_manager, texture_manager, camera_manager)? })
+    }
+}
+
+/// Loads each asset once, however many nodes use it.
+struct Loader<'a> {
+    directory: &'a Path,
+    model_manager: &'a mut ModelManager,
+    texture_manager: &'a mut TextureManager,
+    material_manager: &'a mut MaterialManager,
+    camera_manager: &'a mut CameraManager,
+    models: HashMap<PathBuf, ModelId>,
+    /// The models of each glTF file, by mesh
+    gltf_files: HashMap<PathBuf, Vec<ModelId>>,
+    textures: HashMap<PathBuf, TextureId>,
+}
+
+impl Loader<'_> {
+    /// `path` is the node's children indices from the root, `moved`
+    /// whether any node above it has a transform of its own.
+    fn instantiate(&mut self, description: &NodeDescription, path: &[usize], moved: bool) -> Result<Node, SceneError> {
+        let transform = description.transform;
+        let mut update_fn: UpdateFn = Box::new(|_node, _input_manager, _camera_manager, _delta_time| {});
+
+        let node_type = match &description.kind {
+            NodeKind::Group => NodeType::Group,
+            NodeKind::Mesh(mesh) => {
+                let model_id = self.model(&mesh.model, mesh.mesh)?;
+                let texture_id = mesh.texture.as_ref().map(|texture| self.texture(texture)).transpose()?;
+                NodeType::Mesh(Arc::new(ModelData {
+                    model_id,
+                    texture_id,
+                    sampler: mesh.sampler,
+                    shading: mesh.shading,
+                }))
+            },
+            NodeKind::Light(light) => NodeType::Light(Arc::new(light.clone())),
+            NodeKind::Camera(_) if moved => return Err(SceneError::NestedCamera(path.to_vec())),
+            NodeKind::Camera(camera) => {
+                let camera_id = self.camera_manager.add_camera(Camera::new(
+                    transform.position,
+                    camera.target,
+                    camera.up,
+                    camera.fov.to_radians(),
+                    camera.aspect_ratio,
+                    camera.z_near,
+                    camera.z_far,
+                    camera.speed));
+                if camera.active {
+                    self.camera_manager.set_active_camera(camera_id);
+                }
+                update_fn = Box::new(standard_camera_update);
+                NodeType::Camera(Arc::new(CameraEntityData { camera: camera_id, speed: camera.speed }))
+            },
+        };
+
+        let mut node = Node::new(update_fn, transform, node_type);
+        let moved = moved || transform != Transform::default();
+        for (i, child) in description.children.iter().enumerate() {
+            node.add_child(self.instantiate(child, &[path, &[i]].concat(), moved)?);
+        }
+        Ok(node)
+    }
+
+    fn model(&mut self, path: &Path, mesh: Option<usize>) -> Result<ModelId, SceneError> {
+        let path = self.directory.join(path);
+        if gltf_loader::is_gltf_file(&path) {
+            return self.gltf_mesh(path, mesh.unwrap_or(0));
+        }
+        if let Some(&id) = self.models.get(&path) {
+            return Ok(id);
+        }
+        let id = self.model_manager.load_model_with_materials(&path, self.texture_manager, self.material_manager)
+            .map_err(SceneError::Model)?;
+        self.models.insert(path, id);
+        Ok(id)
+    }
+
+    fn gltf_mesh(&mut self, path: PathBuf, mesh: usize) -> Result<ModelId, SceneError> {
+        if !self.gltf_files.contains_key(&path) {
+            let gltf_scene = GltfScene::load(&path, self.model_manager, self.texture_manager, self.material_manager)
+                .map_err(SceneError::Gltf)?;
+            self.gltf_files.insert(path.clone(), gltf_scene.models);
+        }
+        let models = &self.gltf_files[&path];
+        models.get(mesh).copied()
+            .ok_or_else(|| SceneError::NoSuchMesh { count: models.len(), path, mesh })
+    }
+
+    fn texture(&mut self, path: &Path) -> Result<TextureId, SceneError> {
+        let path = self.directory.join(path);
+        if let Some(&id) = self.textures.get(&path) {
+            return Ok(id);
+        }
+        let id = self.texture_manager.load_texture(&path).map_err(SceneError::Texture)?;
+        self.textures.insert(path, id);
+        Ok(id)
+    }
+}
+
+/// Describes `node`, which is at `path` in children indices from the root.
+/// `moved` says whether any node above it has a transform of its own.
+fn describe(node: &Node,
+            path: &[usize],
+            moved: bool,
+            directory: &Path,
+            model_manager: &ModelManager,
+            texture_manager: &TextureManager,
+            camera_manager: &CameraManager) -> Result<NodeDescription, SceneError> {
+    let mut transform = node.transformation;
+
+    let kind = match &node.node_type {
+        NodeType::Group => NodeKind::Group,
+        NodeType::Mesh(mesh) => match model_manager.get_model_source(mesh.model_id) {
+            Some(source) => NodeKind::Mesh(MeshDescription {
+                model: relative_path(&source.path, directory),
+                mesh: source.mesh,
+                texture: mesh.texture_id.and_then(|id| {
+                    let texture = texture_manager.get_texture_path(id);
+                    if texture.is_none() {
+                        warn!("Leaving out a texture that was not loaded from a file");
+                    }
+                    texture.map(|texture| relative_path(texture, directory))
+                }),
+                sampler: mesh.sampler,
+                shading: mesh.shading,
+            }),
+            None => return Err(SceneError::ModelNotFromFile(path.to_vec())),
+        },
+        NodeType::Light(light) => NodeKind::Light(light.as_ref().clone()),
+        NodeType::Camera(_) if moved => return Err(SceneError::NestedCamera(path.to_vec())),
+        NodeType::Camera(camera_data) => match camera_manager.cameras.get(&camera_data.camera) {
+            Some(camera) => {
+                transform.position = camera.position;
+                NodeKind::Camera(CameraDescription {
+                    target: camera.target,
+                    up: camera.up,
+                    fov: camera.fov.to_degrees(),
+                    aspect_ratio: camera.aspect_ratio,
+                    z_near: camera.z_near,
+                    z_far: camera.z_far,
+                    speed: camera_data.speed,
+                    active: camera_manager.active_camera == Some(camera_data.camera),
+                })
+            },
+            None => NodeKind::Group,
+        },
+    };
+
+    let moved = moved || node.transformation != Transform::default();
+    Ok(NodeDescription {
+        transform,
+        kind,
+        children: node.get_children().iter().enumerate()
+            .map(|(i, child)| describe(child, &[path, &[i]].concat(), moved, directory, model_manager, texture_manager, camera_manager))
+            .collect::<Result<_, _>>()?,
+    })
+}
+
+/// `path` relative to `directory`, so a scene file keeps working when it
+/// is moved along with its assets. Falls back to `path` itself if either
+/// doesn't exist.
+fn relative_path(path: &Path, directory: &Path) -> PathBuf {
+    let (Ok(path), Ok(directory)) = (path.canonicalize(), directory.canonicalize()) else {
+        return path.to_path_buf();
+    };
+
+    let common = path.components().zip(directory.components())
+        .take_while(|(a, b)| a == b)
+        .count();
+    directory.components().skip(common).map(|_| Component::ParentDir)
+        .chain(path.components().skip(common))
+        .collect()
+}
+
+/// Child indices from the root, written like `1/0`.
+fn display_node_path(path: &[usize]) -> String {
+    if path.is_empty() {
+        return "the root".to_string();
+    }
+    path.iter().map(usize::to_string).collect::<Vec<_>>().join("/")
+}
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io { path: PathBuf, source: std::io::Error },
+    Parse { path: PathBuf, source: Box<ron::error::SpannedError> },
+    Serialize { path: PathBuf, source: ron::Error },
+    Model(ObjError),
+    Gltf(GltfError),
+    NoSuchMesh { path: PathBuf, mesh: usize, count: usize },
+    Texture(TextureError),
+    /// The mesh at this node path uses a model that was added directly
+    ModelNotFromFile(Vec<usize>),
+    /// The camera at this node path is under a node with a transform,
+    /// which cameras don't follow
+    NestedCamera(Vec<usize>),
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneError::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            SceneError::Parse { path, source } => write!(f, "{}:{}", path.display(), source),
+            SceneError::Serialize { path, source } => write!(f, "{}: {}", path.display(), source),
+            SceneError::Model(source) => write!(f, "{}", source),
+            SceneError::Gltf(source) => write!(f, "{}", source),
+            SceneError::NoSuchMesh { path, mesh, count } => write!(f, "{}: no mesh {}, the file has {}", path.display(), mesh, count),
+            SceneError::Texture(source) => write!(f, "{}", source),
+            SceneError::ModelNotFromFile(node) => write!(f, "the mesh at {} has a model that was not loaded from a file", display_node_path(node)),
+            SceneError::NestedCamera(node) => write!(f, "the camera at {} is under a node that moves it, cameras only follow their own position", display_node_path(node)),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SceneError::Io { source, .. } => Some(source),
+            SceneError::Parse { source, .. } => Some(source.as_ref()),
+            SceneError::Serialize { source, .. } => Some(source),
+            SceneError::Model(source) => Some(source),
+            SceneError::Gltf(source) => Some(source),
+            SceneError::Texture(source) => Some(source),
+            _ => None,
+        }
+    }
+}
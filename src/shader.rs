@@ -0,0 +1,290 @@
+use glam::{Mat3, Mat4, Vec2, Vec3, Vec4};
+use serde::{Deserialize, Serialize};
+
+use crate::{renderer::Color, texture::{Sampler, Texture}, material::Material, light::SceneLight};
+
+/// Per-vertex outputs of the vertex stage. The rasterizer only ever blends
+/// them, so anything that can be scaled and summed will do.
+pub trait Varyings: Copy + Send + Sync {
+    fn scale(self, factor: f32) -> Self;
+    fn add(self, other: Self) -> Self;
+
+    fn lerp(self, other: Self, t: f32) -> Self {
+        self.scale(1.0 - t).add(other.scale(t))
+    }
+
+    fn interpolate(v0: Self, v1: Self, v2: Self, w0: f32, w1: f32, w2: f32) -> Self {
+        v0.scale(w0).add(v1.scale(w1)).add(v2.scale(w2))
+    }
+}
+
+impl Varyings for () {
+    fn scale(self, _: f32) -> Self {}
+    fn add(self, _: Self) -> Self {}
+}
+
+macro_rules! impl_varyings_for_vector {
+    ($($t:ty),*) => {
+        $(impl Varyings for $t {
+            fn scale(self, factor: f32) -> Self {
+                self * factor
+            }
+
+            fn add(self, other: Self) -> Self {
+                self + other
+            }
+        })*
+    };
+}
+
+impl_varyings_for_vector!(f32, Vec2, Vec3, Vec4);
+
+macro_rules! impl_varyings_for_tuple {
+    ($(($($name:ident $idx:tt),+)),*) => {
+        $(impl<$($name: Varyings),+> Varyings for ($($name,)+) {
+            fn scale(self, factor: f32) -> Self {
+                ($(self.$idx.scale(factor),)+)
+            }
+
+            fn add(self, other: Self) -> Self {
+                ($(self.$idx.add(other.$idx),)+)
+            }
+        })*
+    };
+}
+
+impl_varyings_for_tuple!((A 0, B 1), (A 0, B 1, C 2), (A 0, B 1, C 2, D 3));
+
+/// Everything the vertex stage gets to see about a single face corner.
+#[derive(Debug, Clone, Copy)]
+pub struct VertexInput {
+    pub position: Vec3,
+    pub tex_coord: Vec2,
+    pub normal: Vec3,
+    /// Normal of the face this corner belongs to, in model space
+    pub face_normal: Vec3,
+}
+
+/// A covered pixel that passed the depth test.
+#[derive(Debug, Clone, Copy)]
+pub struct Fragment<V> {
+    /// Pixel coordinates and depth
+    pub position: Vec3,
+    pub varyings: V,
+    /// How much the varyings change one pixel over in x and in y, for
+    /// picking texture detail
+    pub ddx: V,
+    pub ddy: V,
+}
+
+/// Shaders are shared between the render threads, hence `Sync`.
+pub trait Shader: Sync {
+    type Varyings: Varyings;
+
+    /// Returns the clip space position of the vertex and the values that
+    /// get interpolated across the triangle.
+    fn vertex(&self, vertex: &VertexInput) -> (Vec4, Self::Varyings);
+
+    /// Returns the color of the fragment, or `None` to discard it.
+    fn fragment(&self, fragment: &Fragment<Self::Varyings>) -> Option<Color>;
+}
+
+/// How a mesh is lit, picked per mesh in `scene::ModelData`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ShadingMode {
+    /// One normal per face
+    #[default]
+    Flat,
+    /// Vertex normals, lit per vertex
+    Gouraud,
+    /// Vertex normals, lit per pixel with Blinn-Phong specular
+    Phong,
+}
+
+/// What a mesh is made of: constant factors, each optionally modulated by
+/// a texture map.
+#[derive(Clone, Copy)]
+pub struct Surface<'a> {
+    pub diffuse: Vec3,
+    pub diffuse_map: Option<&'a Texture>,
+    pub specular: Vec3,
+    pub specular_map: Option<&'a Texture>,
+    pub shininess: f32,
+    pub opacity: f32,
+    /// Used for every map
+    pub sampler: Sampler,
+}
+
+impl<'a> Surface<'a> {
+    /// A plain texture with the default highlight of the Phong shader.
+    pub fn textured(texture: Option<&'a Texture>, sampler: Sampler) -> Self {
+        Self {
+            diffuse: Vec3::ONE,
+            diffuse_map: texture,
+            specular: Vec3::splat(0.5),
+            specular_map: None,
+            shininess: 32.0,
+            opacity: 1.0,
+            sampler,
+        }
+    }
+
+    /// `texture` looks up the maps referenced by the material.
+    pub fn from_material(material: &Material, texture: impl Fn(crate::texture::TextureId) -> &'a Texture) -> Self {
+        Self {
+            diffuse: material.diffuse,
+            diffuse_map: material.diffuse_map.map(&texture),
+            specular: material.specular,
+            specular_map: material.specular_map.map(&texture),
+            shininess: material.shininess,
+            opacity: material.opacity,
+            sampler: material.sampler,
+        }
+    }
+
+    /// Diffuse color and alpha at `uv`, which changes by `ddx` and `ddy`
+    /// between neighbouring pixels.
+    pub fn diffuse_at(&self, uv: Vec2, ddx: Vec2, ddy: Vec2) -> (Vec3, u8) {
+        let (color, alpha) = match self.diffuse_map {
+            Some(texture) => {
+                let texel = texture.sample_grad(uv, ddx, ddy, &self.sampler);
+                (texel.to_vec3(), texel.a as f32)
+            },
+            None => (Vec3::ONE, 255.0),
+        };
+        (self.diffuse * color, (alpha * self.opacity).round() as u8)
+    }
+
+    pub fn specular_at(&self, uv: Vec2, ddx: Vec2, ddy: Vec2) -> Vec3 {
+        match self.specular_map {
+            Some(texture) => self.specular * texture.sample_grad(uv, ddx, ddy, &self.sampler).to_vec3(),
+            None => self.specular,
+        }
+    }
+}
+
+/// Matrices and lighting shared by the built-in shaders. Lighting is done
+/// in world space.
+pub struct Uniforms<'a> {
+    pub model: Mat4,
+    pub view_projection: Mat4,
+    /// Inverse transpose of the model matrix, keeps normals perpendicular
+    /// to their surface under non-uniform scaling
+    pub normal_matrix: Mat3,
+    pub surface: Surface<'a>,
+    pub lights: &'a [SceneLight],
+    pub camera_position: Vec3,
+}
+
+impl<'a> Uniforms<'a> {
+    pub fn new(model: Mat4, view: Mat4, projection: Mat4, surface: Surface<'a>, lights: &'a [SceneLight], camera_position: Vec3) -> Self {
+        Self {
+            model,
+            view_projection: projection * view,
+            normal_matrix: Mat3::from_mat4(model).inverse().transpose(),
+            surface,
+            lights,
+            camera_position,
+        }
+    }
+
+    fn world_normal(&self, normal: Vec3) -> Vec3 {
+        (self.normal_matrix * normal).normalize_or_zero()
+    }
+
+    /// Sum of the Lambertian terms of every light.
+    fn diffuse(&self, position: Vec3, normal: Vec3) -> Vec3 {
+        self.lights.iter()
+            .map(|light| {
+                let (to_light, radiance) = light.illuminate(position);
+                radiance * normal.dot(to_light).max(0.0)
+            })
+            .sum()
+    }
+}
+
+/// Textured, lit per vertex, from either the face or the vertex normals.
+pub struct VertexLitShader<'a> {
+    pub uniforms: Uniforms<'a>,
+    /// Light each face evenly rather than following the mesh's own normals
+    pub face_normals: bool,
+}
+
+impl<'a> VertexLitShader<'a> {
+    /// Flat shaded facets.
+    pub fn flat(uniforms: Uniforms<'a>) -> Self {
+        Self { uniforms, face_normals: true }
+    }
+
+    /// Gouraud shading from the vertex normals.
+    pub fn gouraud(uniforms: Uniforms<'a>) -> Self {
+        Self { uniforms, face_normals: false }
+    }
+}
+
+impl Shader for VertexLitShader<'_> {
+    /// Texture coordinates and incoming light
+    type Varyings = (Vec2, Vec3);
+
+    fn vertex(&self, vertex: &VertexInput) -> (Vec4, Self::Varyings) {
+        let u = &self.uniforms;
+        let world_position = u.model * vertex.position.extend(1.0);
+        let normal = if self.face_normals { vertex.face_normal } else { vertex.normal };
+        let light = u.diffuse(world_position.truncate(), u.world_normal(normal));
+        (u.view_projection * world_position, (vertex.tex_coord, light))
+    }
+
+    fn fragment(&self, fragment: &Fragment<Self::Varyings>) -> Option<Color> {
+        let (uv, light) = fragment.varyings;
+        let (color, alpha) = self.uniforms.surface.diffuse_at(uv, fragment.ddx.0, fragment.ddy.0);
+        Some(Color::from_vec3(color * light, alpha))
+    }
+}
+
+/// Textured, lit per pixel with a Blinn-Phong specular highlight.
+pub struct PhongShader<'a> {
+    pub uniforms: Uniforms<'a>,
+}
+
+impl<'a> PhongShader<'a> {
+    pub fn new(uniforms: Uniforms<'a>) -> Self {
+        Self { uniforms }
+    }
+}
+
+impl Shader for PhongShader<'_> {
+    /// Texture coordinates, world space normal and world space position
+    type Varyings = (Vec2, Vec3, Vec3);
+
+    fn vertex(&self, vertex: &VertexInput) -> (Vec4, Self::Varyings) {
+        let u = &self.uniforms;
+        let world_position = u.model * vertex.position.extend(1.0);
+        let varyings = (vertex.tex_coord, u.world_normal(vertex.normal), world_position.truncate());
+        (u.view_projection * world_position, varyings)
+    }
+
+    fn fragment(&self, fragment: &Fragment<Self::Varyings>) -> Option<Color> {
+        let u = &self.uniforms;
+        let (uv, normal, position) = fragment.varyings;
+        let normal = normal.normalize_or_zero();
+        let to_camera = (u.camera_position - position).normalize_or_zero();
+
+        let surface = &u.surface;
+        let mut diffuse = Vec3::ZERO;
+        let mut specular = Vec3::ZERO;
+        for light in u.lights {
+            let (to_light, radiance) = light.illuminate(position);
+            let lambert = normal.dot(to_light);
+            if lambert <= 0.0 {
+                continue;
+            }
+            let half_way = (to_light + to_camera).normalize_or_zero();
+            diffuse += radiance * lambert;
+            specular += radiance * normal.dot(half_way).max(0.0).powf(surface.shininess);
+        }
+
+        let (ddx, ddy) = (fragment.ddx.0, fragment.ddy.0);
+        let (color, alpha) = surface.diffuse_at(uv, ddx, ddy);
+        Some(Color::from_vec3(color * diffuse + surface.specular_at(uv, ddx, ddy) * specular, alpha))
+    }
+}
@@ -1,63 +1,278 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, fmt, io::Cursor, path::{Path, PathBuf}};
 
 use crate::renderer::Color;
-use glam::Vec2;
+use glam::{Vec2, Vec4};
+use serde::{Deserialize, Serialize};
 
 pub struct Texture {
     pub width: usize,
     pub height: usize,
     pub pixels: Vec<u8>,
+    /// Successively halved copies down to 1x1, see `build_mips`
+    pub mips: Vec<Texture>,
 }
 
 impl Texture {
-    pub fn sample(&self, uv: Vec2) -> Color {
-        // Clamp the values of u and v to ensure they're within the texture bounds
-        let x = (uv.x.clamp(0.0, 1.0) * self.width as f32) as usize;
-        let y = (uv.y.clamp(0.0, 1.0) * self.height as f32) as usize;
+    /// `pixels` holds `width * height` RGBA texels, row by row.
+    pub fn new(width: usize, height: usize, pixels: Vec<u8>) -> Self {
+        Self {
+            width,
+            height,
+            pixels,
+            mips: Vec::new(),
+        }
+    }
+
+    pub fn from_image(image: image::DynamicImage) -> Self {
+        let image = image.to_rgba8();
+        Self::new(image.width() as usize, image.height() as usize, image.into_raw())
+    }
+
+    /// Fills `mips` by repeatedly averaging 2x2 blocks of texels. Odd
+    /// sizes round down, the last row or column folding into the one
+    /// before it.
+    pub fn build_mips(&mut self) {
+        self.mips.clear();
+        let (mut width, mut height) = (self.width, self.height);
+        while width > 1 || height > 1 {
+            let source = self.mips.last().unwrap_or(self);
+            let next = source.downsample();
+            (width, height) = (next.width, next.height);
+            self.mips.push(next);
+        }
+    }
 
-        // Ensure the index does not go beyond the texture's pixel array
-        let clamped_x = x.min(self.width - 1);
-        let clamped_y = y.min(self.height - 1);
+    fn downsample(&self) -> Texture {
+        let width = (self.width / 2).max(1);
+        let height = (self.height / 2).max(1);
+        let mut pixels = Vec::with_capacity(width * height * 4);
 
-        let index = (clamped_y * self.width + clamped_x) * 4;
+        for y in 0..height {
+            for x in 0..width {
+                let mut sum = [0u32; 4];
+                let mut count = 0;
+                // Covers 2x2 texels, or 3 along an axis at odd edges
+                let xs = 2 * x..if x + 1 == width { self.width } else { 2 * x + 2 };
+                let ys = 2 * y..if y + 1 == height { self.height } else { 2 * y + 2 };
+                for sy in ys {
+                    for sx in xs.clone() {
+                        let index = (sy * self.width + sx) * 4;
+                        for (channel, value) in sum.iter_mut().zip(&self.pixels[index..index + 4]) {
+                            *channel += *value as u32;
+                        }
+                        count += 1;
+                    }
+                }
+                pixels.extend(sum.map(|channel| ((channel + count / 2) / count) as u8));
+            }
+        }
+
+        Texture::new(width, height, pixels)
+    }
+
+    /// Level 0 is the texture itself.
+    pub fn level(&self, level: usize) -> &Texture {
+        match level {
+            0 => self,
+            _ => &self.mips[(level - 1).min(self.mips.len() - 1)],
+        }
+    }
 
-        // Assuming the pixel buffer is laid out as RGBA
+    /// Like `sample`, but picks the level of detail from how much `uv`
+    /// changes between neighbouring pixels, `ddx` and `ddy`.
+    pub fn sample_grad(&self, uv: Vec2, ddx: Vec2, ddy: Vec2, sampler: &Sampler) -> Color {
+        if sampler.mip_filter == MipFilter::None || self.mips.is_empty() {
+            return self.sample(uv, sampler);
+        }
+
+        // Footprint of a pixel, in texels of the full size texture
+        let size = Vec2::new(self.width as f32, self.height as f32);
+        let footprint = (ddx * size).length().max((ddy * size).length());
+        let lod = footprint.log2().clamp(0.0, self.mips.len() as f32);
+        if !lod.is_finite() {
+            return self.sample(uv, sampler);
+        }
+
+        match sampler.mip_filter {
+            MipFilter::Linear if lod.fract() > 0.0 => {
+                let lower = lod.floor() as usize;
+                let a = self.level(lower).sample(uv, sampler);
+                let b = self.level(lower + 1).sample(uv, sampler);
+                let color = Vec4::from(a).lerp(Vec4::from(b), lod.fract()).round();
+                Color::new(color.x as u8, color.y as u8, color.z as u8, color.w as u8)
+            },
+            _ => self.level(lod.round() as usize).sample(uv, sampler),
+        }
+    }
+
+    /// Looks up the texture at `uv`, where (0, 0) is the first texel in
+    /// memory, filtered and wrapped the way `sampler` says.
+    pub fn sample(&self, uv: Vec2, sampler: &Sampler) -> Color {
+        let x = uv.x * self.width as f32;
+        let y = uv.y * self.height as f32;
+
+        match sampler.filter {
+            Filter::Nearest => self.texel(
+                sampler.wrap_u.apply(x.floor() as i64, self.width),
+                sampler.wrap_v.apply(y.floor() as i64, self.height)),
+            Filter::Bilinear => {
+                // Texel centers sit at half-integer coordinates
+                let (x, y) = (x - 0.5, y - 0.5);
+                let (x0, y0) = (x.floor(), y.floor());
+                let (tx, ty) = (x - x0, y - y0);
+                let (x0, y0) = (x0 as i64, y0 as i64);
+
+                let xs = [sampler.wrap_u.apply(x0, self.width), sampler.wrap_u.apply(x0 + 1, self.width)];
+                let ys = [sampler.wrap_v.apply(y0, self.height), sampler.wrap_v.apply(y0 + 1, self.height)];
+                let texel = |x, y| Vec4::from(self.texel(x, y));
+
+                let top = texel(xs[0], ys[0]).lerp(texel(xs[1], ys[0]), tx);
+                let bottom = texel(xs[0], ys[1]).lerp(texel(xs[1], ys[1]), tx);
+                let color = top.lerp(bottom, ty).round();
+                Color::new(color.x as u8, color.y as u8, color.z as u8, color.w as u8)
+            },
+        }
+    }
+
+    fn texel(&self, x: usize, y: usize) -> Color {
+        // The pixel buffer is laid out as RGBA
+        let index = (y * self.width + x) * 4;
         Color::new(
-            self.pixels[index],     // Red
-            self.pixels[index + 1], // Green
-            self.pixels[index + 2], // Blue
-            self.pixels[index + 3], // Alpha
+            self.pixels[index],
+            self.pixels[index + 1],
+            self.pixels[index + 2],
+            self.pixels[index + 3],
         )
     }
 }
 
+/// How texels get blended when sampling.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Filter {
+    /// The texel under the sample point
+    #[default]
+    Nearest,
+    /// Weighted average of the four closest texels
+    Bilinear,
+}
+
+/// What happens to texture coordinates outside of [0, 1].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Wrap {
+    /// Stretch the edge texels
+    #[default]
+    Clamp,
+    /// Tile the texture
+    Repeat,
+    /// Tile the texture, flipping every other copy
+    MirroredRepeat,
+}
+
+impl Wrap {
+    /// Maps a texel index along an axis of `size` texels into the texture.
+    fn apply(self, index: i64, size: usize) -> usize {
+        let size = size as i64;
+        let index = match self {
+            Wrap::Clamp => index.clamp(0, size - 1),
+            Wrap::Repeat => index.rem_euclid(size),
+            Wrap::MirroredRepeat => {
+                let index = index.rem_euclid(2 * size);
+                if index < size { index } else { 2 * size - 1 - index }
+            },
+        };
+        index as usize
+    }
+}
+
+/// How mip levels are used by `Texture::sample_grad`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MipFilter {
+    /// Always read the full size texture
+    #[default]
+    None,
+    /// Read the closest level
+    Nearest,
+    /// Blend the two closest levels. Trilinear filtering when combined
+    /// with `Filter::Bilinear`.
+    Linear,
+}
+
+/// Filtering and per axis wrapping used to read a texture.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Sampler {
+    pub filter: Filter,
+    pub mip_filter: MipFilter,
+    pub wrap_u: Wrap,
+    pub wrap_v: Wrap,
+}
+
+impl Sampler {
+    pub fn new(filter: Filter, wrap: Wrap) -> Self {
+        Self { filter, mip_filter: MipFilter::None, wrap_u: wrap, wrap_v: wrap }
+    }
+
+    /// Bilinear filtering blended across mip levels.
+    pub fn trilinear(wrap: Wrap) -> Self {
+        Self { filter: Filter::Bilinear, mip_filter: MipFilter::Linear, wrap_u: wrap, wrap_v: wrap }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct TextureId(usize);
 
 pub struct TextureManager {
     textures: HashMap<TextureId, Texture>,
+    /// Files the textures were loaded from
+    paths: HashMap<TextureId, PathBuf>,
+}
+
+impl Default for TextureManager {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl TextureManager {
     pub fn new() -> Self {
         TextureManager {
             textures: HashMap::new(),
+            paths: HashMap::new(),
         }
     }
 
-    pub fn load_texture(&mut self, filename: &str) -> TextureId {
-        // Validate filename is tga
-        if !filename.ends_with(".tga") {
-            panic!("Invalid file type. Must be .tga");
-        }
+    /// Loads any image format the `image` crate can decode. The format is
+    /// detected from the file's contents, not its extension.
+    pub fn load_texture<P: AsRef<Path>>(&mut self, filename: P) -> Result<TextureId, TextureError> {
+        let path = filename.as_ref();
+        let image = image::io::Reader::open(path)
+            .and_then(|reader| reader.with_guessed_format())
+            .map_err(|source| TextureError::Io { path: path.to_path_buf(), source })?
+            .decode()
+            .map_err(|source| TextureError::Decode { path: Some(path.to_path_buf()), source })?;
 
-        let image = image::open(filename).unwrap().to_rgba8();
+        let texture_id = self.add_texture(Texture::from_image(image));
+        self.paths.insert(texture_id, path.to_path_buf());
+        Ok(texture_id)
+    }
 
-        let texture = Texture {
-            width: image.width() as usize,
-            height: image.height() as usize,
-            pixels: image.into_raw(),
-        };
+    /// Decodes an encoded image held in memory, such as one embedded in
+    /// another file.
+    pub fn load_texture_from_memory(&mut self, bytes: &[u8]) -> Result<TextureId, TextureError> {
+        let image = image::io::Reader::new(Cursor::new(bytes))
+            .with_guessed_format()
+            .map_err(image::ImageError::IoError)
+            .and_then(|reader| reader.decode())
+            .map_err(|source| TextureError::Decode { path: None, source })?;
+
+        Ok(self.add_texture(Texture::from_image(image)))
+    }
+
+    /// Builds the texture's mip chain unless it already has one.
+    pub fn add_texture(&mut self, mut texture: Texture) -> TextureId {
+        if texture.mips.is_empty() {
+            texture.build_mips();
+        }
 
         let texture_id = TextureId(self.textures.len());
         self.textures.insert(texture_id, texture);
@@ -68,4 +283,153 @@ impl TextureManager {
     pub fn get_texture(&self, texture_id: TextureId) -> &Texture {
         self.textures.get(&texture_id).unwrap()
     }
+
+    /// The file the texture was loaded from, `None` for textures decoded
+    /// from memory or added directly.
+    pub fn get_texture_path(&self, texture_id: TextureId) -> Option<&Path> {
+        self.paths.get(&texture_id).map(PathBuf::as_path)
+    }
+}
+
+#[derive(Debug)]
+pub enum TextureError {
+    Io { path: PathBuf, source: std::io::Error },
+    /// `path` is `None` for images decoded from memory
+    Decode { path: Option<PathBuf>, source: image::ImageError },
+}
+
+impl fmt::Display for TextureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TextureError::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            TextureError::Decode { path: Some(path), source } => write!(f, "{}: {}", path.display(), source),
+            TextureError::Decode { path: None, source } => write!(f, "in-memory image: {}", source),
+        }
+    }
+}
+
+impl std::error::Error for TextureError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TextureError::Io { source, .. } => Some(source),
+            TextureError::Decode { source, .. } => Some(source),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 2x1 image, red then blue, encoded as PNG.
+    fn png_bytes() -> Vec<u8> {
+        let image = image::RgbaImage::from_raw(2, 1, vec![255, 0, 0, 255, 0, 0, 255, 255]).unwrap();
+        let mut bytes = Cursor::new(Vec::new());
+        image.write_to(&mut bytes, image::ImageFormat::Png).unwrap();
+        bytes.into_inner()
+    }
+
+    #[test]
+    fn decodes_from_memory() {
+        let mut textures = TextureManager::new();
+        let texture_id = textures.load_texture_from_memory(&png_bytes()).unwrap();
+        let texture = textures.get_texture(texture_id);
+        assert_eq!((texture.width, texture.height), (2, 1));
+        assert_eq!(texture.pixels, [255, 0, 0, 255, 0, 0, 255, 255]);
+        assert_eq!(textures.get_texture_path(texture_id), None);
+    }
+
+    #[test]
+    fn format_comes_from_the_contents() {
+        let path = std::env::temp_dir().join(format!("rs_sloth_renderer_{}_png.tga", std::process::id()));
+        std::fs::write(&path, png_bytes()).unwrap();
+        let mut textures = TextureManager::new();
+        let result = textures.load_texture(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        let texture_id = result.unwrap();
+        assert_eq!(textures.get_texture(texture_id).pixels, [255, 0, 0, 255, 0, 0, 255, 255]);
+        assert_eq!(textures.get_texture_path(texture_id), Some(path.as_path()));
+    }
+
+    #[test]
+    fn garbage_does_not_decode() {
+        let result = TextureManager::new().load_texture_from_memory(b"not an image at all");
+        assert!(matches!(result, Err(TextureError::Decode { path: None, .. })), "{:?}", result);
+    }
+
+    #[test]
+    fn clamp_stretches_the_edges() {
+        let indices: Vec<usize> = (-2..6).map(|i| Wrap::Clamp.apply(i, 3)).collect();
+        assert_eq!(indices, [0, 0, 0, 1, 2, 2, 2, 2]);
+    }
+
+    #[test]
+    fn repeat_tiles() {
+        let indices: Vec<usize> = (-4..7).map(|i| Wrap::Repeat.apply(i, 3)).collect();
+        assert_eq!(indices, [2, 0, 1, 2, 0, 1, 2, 0, 1, 2, 0]);
+    }
+
+    #[test]
+    fn mirrored_repeat_flips_every_other_tile() {
+        let indices: Vec<usize> = (-4..7).map(|i| Wrap::MirroredRepeat.apply(i, 3)).collect();
+        assert_eq!(indices, [2, 2, 1, 0, 0, 1, 2, 2, 1, 0, 0]);
+    }
+
+    #[test]
+    fn bilinear_at_a_texel_center_is_that_texel() {
+        let pixels: Vec<u8> = (0..4 * 3 * 4).map(|i| (i * 5) as u8).collect();
+        let texture = Texture::new(4, 3, pixels);
+        let sampler = Sampler::new(Filter::Bilinear, Wrap::Repeat);
+        for y in 0..3 {
+            for x in 0..4 {
+                let uv = Vec2::new((x as f32 + 0.5) / 4.0, (y as f32 + 0.5) / 3.0);
+                let (sampled, texel) = (texture.sample(uv, &sampler), texture.texel(x, y));
+                assert_eq!(Vec4::from(sampled), Vec4::from(texel), "texel ({}, {})", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn odd_sizes_fold_the_last_texels_together() {
+        let mut texture = Texture::new(3, 1, [0, 30, 90].iter().flat_map(|&v| [v, v, v, 255]).collect());
+        texture.build_mips();
+        assert_eq!(texture.mips.len(), 1);
+        assert_eq!((texture.mips[0].width, texture.mips[0].height), (1, 1));
+        assert_eq!(texture.mips[0].pixels, [40, 40, 40, 255]);
+    }
+
+    /// A black and a light gray texel, averaging to 100 in the 1x1 level.
+    fn black_and_white() -> Texture {
+        let mut texture = Texture::new(2, 1, vec![0, 0, 0, 255, 200, 200, 200, 255]);
+        texture.build_mips();
+        texture
+    }
+
+    fn sample_grad(texture: &Texture, ddx: Vec2, ddy: Vec2) -> Vec4 {
+        let sampler = Sampler { mip_filter: MipFilter::Linear, ..Sampler::default() };
+        Vec4::from(texture.sample_grad(Vec2::new(0.25, 0.5), ddx, ddy, &sampler))
+    }
+
+    #[test]
+    fn one_texel_per_pixel_reads_the_full_size() {
+        let texture = black_and_white();
+        assert_eq!(sample_grad(&texture, Vec2::new(0.5, 0.0), Vec2::new(0.0, 1.0)), Vec4::new(0.0, 0.0, 0.0, 255.0));
+    }
+
+    #[test]
+    fn fractional_lod_blends_two_levels() {
+        let texture = black_and_white();
+        // sqrt(2) texels per pixel, halfway between 0 and 100
+        let ddx = Vec2::new(std::f32::consts::SQRT_2 / 2.0, 0.0);
+        let color = sample_grad(&texture, ddx, Vec2::ZERO);
+        assert!(color.abs_diff_eq(Vec4::new(50.0, 50.0, 50.0, 255.0), 1.0), "{}", color);
+    }
+
+    #[test]
+    fn non_finite_lod_reads_the_full_size() {
+        let texture = black_and_white();
+        let color = sample_grad(&texture, Vec2::NAN, Vec2::NAN);
+        assert_eq!(color, Vec4::new(0.0, 0.0, 0.0, 255.0));
+    }
 }
@@ -0,0 +1,229 @@
+use std::time::{Duration, Instant};
+
+/// Where the application loop gets the time from. Only differences between
+/// readings matter.
+pub trait Clock {
+    fn now(&self) -> Duration;
+}
+
+/// Wall clock time since the clock was created.
+#[derive(Debug, Clone, Copy)]
+pub struct SystemClock {
+    start: Instant,
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SystemClock {
+    pub fn new() -> Self {
+        Self { start: Instant::now() }
+    }
+
+    /// The `Instant` a reading of this clock corresponds to, for waiting
+    /// on it.
+    pub fn instant_at(&self, time: Duration) -> Instant {
+        self.start + time
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+}
+
+/// A clock that only moves when told to, for driving the loop without a
+/// window or in tests.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ManualClock {
+    now: Duration,
+}
+
+impl ManualClock {
+    pub fn advance(&mut self, duration: Duration) {
+        self.now += duration;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Duration {
+        self.now
+    }
+}
+
+/// What to do this frame: run `updates` fixed updates, then render with
+/// the state blended `alpha` of the way from the one before the last
+/// update to the last one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameTiming {
+    pub updates: u32,
+    pub alpha: f32,
+}
+
+/// Splits the time between frames into fixed update steps, so updates see
+/// the same `delta_time` however fast frames get rendered. Leftover time
+/// carries over to the next frame and becomes the interpolation factor.
+///
+/// ```
+/// use std::time::Duration;
+/// use rs_sloth_renderer::timestep::{FixedTimestep, ManualClock};
+///
+/// let mut timestep = FixedTimestep::new(ManualClock::default(), 50.0);
+/// // The first frame only starts the clock
+/// assert_eq!(timestep.begin_frame().unwrap().updates, 0);
+///
+/// timestep.clock_mut().advance(Duration::from_millis(70));
+/// let timing = timestep.begin_frame().unwrap();
+/// assert_eq!(timing.updates, 3);
+/// assert!((timing.alpha - 0.5).abs() < 1e-4);
+/// assert_eq!(timestep.delta_time(), 0.02);
+/// ```
+#[derive(Debug)]
+pub struct FixedTimestep<C: Clock = SystemClock> {
+    clock: C,
+    step: Duration,
+    /// Shortest time between frames, if capped
+    frame_time: Option<Duration>,
+    /// Time that would need more updates than this per frame gets dropped,
+    /// so a long stall doesn't snowball
+    max_updates: u32,
+    accumulator: Duration,
+    last_frame: Option<Duration>,
+}
+
+impl<C: Clock> FixedTimestep<C> {
+    /// # Panics
+    ///
+    /// If `updates_per_second` isn't positive and finite, or is too high
+    /// for a step to last a nanosecond.
+    pub fn new(clock: C, updates_per_second: f32) -> Self {
+        let step = period("updates_per_second", updates_per_second);
+        assert!(!step.is_zero(), "updates_per_second of {} is too high", updates_per_second);
+        Self {
+            clock,
+            step,
+            frame_time: None,
+            max_updates: 10,
+            accumulator: Duration::ZERO,
+            last_frame: None,
+        }
+    }
+
+    /// Limits rendering to `frames_per_second`, or lifts the limit.
+    ///
+    /// # Panics
+    ///
+    /// If `frames_per_second` isn't positive and finite.
+    pub fn set_frame_cap(&mut self, frames_per_second: Option<f32>) {
+        self.frame_time = frames_per_second.map(|fps| period("frames_per_second", fps));
+    }
+
+    pub fn set_max_updates(&mut self, max_updates: u32) {
+        self.max_updates = max_updates;
+    }
+
+    /// Seconds simulated by each update.
+    pub fn delta_time(&self) -> f32 {
+        self.step.as_secs_f32()
+    }
+
+    pub fn clock(&self) -> &C {
+        &self.clock
+    }
+
+    pub fn clock_mut(&mut self) -> &mut C {
+        &mut self.clock
+    }
+
+    /// Clock reading from which the next frame may start.
+    pub fn next_frame_time(&self) -> Duration {
+        match (self.last_frame, self.frame_time) {
+            (Some(last_frame), Some(frame_time)) => last_frame + frame_time,
+            _ => self.clock.now(),
+        }
+    }
+
+    /// Starts a frame, or returns `None` if the frame cap says it is too
+    /// early for one.
+    pub fn begin_frame(&mut self) -> Option<FrameTiming> {
+        let now = self.clock.now();
+        if now < self.next_frame_time() {
+            return None;
+        }
+
+        let elapsed = self.last_frame.map_or(Duration::ZERO, |last_frame| now - last_frame);
+        self.last_frame = Some(now);
+        self.accumulator = (self.accumulator + elapsed).min(self.step * self.max_updates);
+
+        let mut updates = 0;
+        while self.accumulator >= self.step {
+            self.accumulator -= self.step;
+            updates += 1;
+        }
+
+        Some(FrameTiming {
+            updates,
+            alpha: self.accumulator.as_secs_f32() / self.step.as_secs_f32(),
+        })
+    }
+}
+
+/// Time between two events happening `rate` times a second.
+fn period(name: &str, rate: f32) -> Duration {
+    assert!(rate.is_finite() && rate > 0.0, "{} must be positive and finite, got {}", name, rate);
+    Duration::from_secs_f64(1.0 / rate as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 100 updates a second, with the first frame already started.
+    fn started(set_up: impl FnOnce(&mut FixedTimestep<ManualClock>)) -> FixedTimestep<ManualClock> {
+        let mut timestep = FixedTimestep::new(ManualClock::default(), 100.0);
+        set_up(&mut timestep);
+        assert_eq!(timestep.begin_frame().unwrap().updates, 0);
+        timestep
+    }
+
+    fn advance(timestep: &mut FixedTimestep<ManualClock>, milliseconds: u64) -> Option<FrameTiming> {
+        timestep.clock_mut().advance(Duration::from_millis(milliseconds));
+        timestep.begin_frame()
+    }
+
+    fn assert_timing(timing: Option<FrameTiming>, updates: u32, alpha: f32) {
+        let timing = timing.expect("the frame should have started");
+        assert_eq!(timing.updates, updates, "{:?}", timing);
+        assert!((timing.alpha - alpha).abs() < 1e-4, "{:?}", timing);
+    }
+
+    #[test]
+    fn frame_cap_waits_for_the_next_frame_time() {
+        let mut timestep = started(|timestep| timestep.set_frame_cap(Some(50.0)));
+        assert_eq!(timestep.next_frame_time(), Duration::from_millis(20));
+        assert_eq!(advance(&mut timestep, 19), None);
+        assert_timing(advance(&mut timestep, 1), 2, 0.0);
+        assert_eq!(timestep.next_frame_time(), Duration::from_millis(40));
+    }
+
+    #[test]
+    fn long_stall_is_clamped_to_max_updates() {
+        let mut timestep = started(|timestep| timestep.set_max_updates(3));
+        assert_timing(advance(&mut timestep, 1000), 3, 0.0);
+        // The dropped time is gone for good
+        assert_timing(advance(&mut timestep, 10), 1, 0.0);
+    }
+
+    #[test]
+    fn leftover_time_carries_over() {
+        let mut timestep = started(|_| {});
+        assert_timing(advance(&mut timestep, 15), 1, 0.5);
+        assert_timing(advance(&mut timestep, 7), 1, 0.2);
+        assert_timing(advance(&mut timestep, 3), 0, 0.5);
+        assert_timing(advance(&mut timestep, 5), 1, 0.0);
+    }
+}
@@ -0,0 +1,336 @@
+use std::{fmt, fs::File, io::BufWriter, path::PathBuf};
+
+use glam::{Quat, Vec3};
+use image::{codecs::gif::{GifEncoder, Repeat}, Delay, Frame, ImageError};
+use log::info;
+
+use crate::{
+    camera::CameraManager,
+    material::MaterialManager,
+    model::ModelManager,
+    renderer::Renderer,
+    scene::Node,
+    texture::TextureManager,
+};
+
+/// What turns during a turntable.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Spin {
+    /// The active camera circles its target
+    Camera,
+    /// The node spins in place. Children indices leading to it from the
+    /// root, so `[]` is the root itself and `[1, 0]` the first child of its
+    /// second child.
+    Node(Vec<usize>),
+}
+
+/// Renders a full turn around a scene, frame by frame, for reviewing
+/// assets from every side.
+#[derive(Debug, Clone)]
+pub struct Turntable {
+    pub frames: u32,
+    pub spin: Spin,
+    /// For the camera, in world space through its target; for a node, in
+    /// its parent's space through its position
+    pub axis: Vec3,
+    /// Playback rate of the animated GIF
+    pub frames_per_second: u32,
+}
+
+/// Where `Turntable::export` writes the frames. Either can be left out.
+#[derive(Debug, Clone, Default)]
+pub struct TurntableOutput {
+    /// Directory for `frame_00000.png`, `frame_00001.png` and so on
+    pub frame_directory: Option<PathBuf>,
+    pub gif: Option<PathBuf>,
+}
+
+impl Turntable {
+    pub fn new(frames: u32, spin: Spin) -> Self {
+        Self {
+            frames,
+            spin,
+            axis: Vec3::Y,
+            frames_per_second: 30,
+        }
+    }
+
+    /// Radians turned by `frame`. The last frame stops one step short of
+    /// a full turn, so the animation loops without repeating a frame.
+    pub fn angle(&self, frame: u32) -> f32 {
+        std::f32::consts::TAU * frame as f32 / self.frames.max(1) as f32
+    }
+
+    /// Renders every frame with `renderer` and hands it to `frame` along
+    /// with its number. The camera or node is put back where it was
+    /// afterwards, even if `frame` fails.
+    #[allow(clippy::too_many_arguments)]
+    pub fn render<F>(&self,
+                     renderer: &mut Renderer,
+                     root: &mut Node,
+                     model_manager: &ModelManager,
+                     texture_manager: &TextureManager,
+                     material_manager: &MaterialManager,
+                     camera_manager: &mut CameraManager,
+                     mut frame: F) -> Result<(), TurntableError>
+        where F: FnMut(u32, &Renderer) -> Result<(), TurntableError> {
+        self.check(root, camera_manager)?;
+        let axis = self.axis.normalize_or_zero();
+        let mut result = Ok(());
+
+        match &self.spin {
+            Spin::Camera => {
+                let Some(camera) = camera_manager.get_active_camera() else {
+                    return Err(TurntableError::NoActiveCamera);
+                };
+                let (position, target) = (camera.position, camera.target);
+
+                for i in 0..self.frames {
+                    if let Some(camera) = camera_manager.get_active_camera_mut() {
+                        camera.position = target + Quat::from_axis_angle(axis, self.angle(i)) * (position - target);
+                    }
+                    renderer.render(root, model_manager, texture_manager, material_manager, camera_manager);
+                    result = frame(i, renderer);
+                    if result.is_err() {
+                        break;
+                    }
+                }
+
+                if let Some(camera) = camera_manager.get_active_camera_mut() {
+                    camera.position = position;
+                }
+            },
+            Spin::Node(path) => {
+                let Some(rotation) = node_at(root, path).map(|node| node.transformation.rotation) else {
+                    return Err(TurntableError::NoSuchNode(path.clone()));
+                };
+
+                for i in 0..self.frames {
+                    if let Some(node) = node_at(root, path) {
+                        node.transformation.rotation = Quat::from_axis_angle(axis, self.angle(i)) * rotation;
+                    }
+                    renderer.render(root, model_manager, texture_manager, material_manager, camera_manager);
+                    result = frame(i, renderer);
+                    if result.is_err() {
+                        break;
+                    }
+                }
+
+                if let Some(node) = node_at(root, path) {
+                    node.transformation.rotation = rotation;
+                }
+            },
+        }
+
+        result
+    }
+
+    /// Renders the turntable into numbered PNGs, an animated GIF that
+    /// loops forever, or both.
+    #[allow(clippy::too_many_arguments)]
+    pub fn export(&self,
+                  output: &TurntableOutput,
+                  renderer: &mut Renderer,
+                  root: &mut Node,
+                  model_manager: &ModelManager,
+                  texture_manager: &TextureManager,
+                  material_manager: &MaterialManager,
+                  camera_manager: &mut CameraManager) -> Result<(), TurntableError> {
+        // Fail before creating any files
+        self.check(root, camera_manager)?;
+
+        if let Some(directory) = &output.frame_directory {
+            std::fs::create_dir_all(directory)
+                .map_err(|source| TurntableError::Io { path: directory.clone(), source })?;
+        }
+
+        let mut gif = match &output.gif {
+            Some(path) => {
+                let file = File::create(path)
+                    .map_err(|source| TurntableError::Io { path: path.clone(), source })?;
+                let mut encoder = GifEncoder::new_with_speed(BufWriter::new(file), 10);
+                encoder.set_repeat(Repeat::Infinite)
+                    .map_err(|source| TurntableError::Image { path: path.clone(), source })?;
+                Some((path, encoder))
+            },
+            None => None,
+        };
+        let delay = Delay::from_numer_denom_ms(1000, self.frames_per_second.max(1));
+
+        self.render(renderer, root, model_manager, texture_manager, material_manager, camera_manager, |i, renderer| {
+            if let Some(directory) = &output.frame_directory {
+                let path = directory.join(format!("frame_{:05}.png", i));
+                renderer.save(&path).map_err(|source| TurntableError::Image { path, source })?;
+            }
+            if let Some((path, encoder)) = &mut gif {
+                encoder.encode_frame(Frame::from_parts(renderer.to_image(), 0, 0, delay))
+                    .map_err(|source| TurntableError::Image { path: path.to_path_buf(), source })?;
+            }
+            Ok(())
+        })?;
+
+        info!("Turntable exported: {} frames", self.frames);
+        Ok(())
+    }
+
+    /// Whether there are frames to render and something to turn.
+    fn check(&self, root: &mut Node, camera_manager: &CameraManager) -> Result<(), TurntableError> {
+        if self.frames == 0 {
+            return Err(TurntableError::NoFrames);
+        }
+        match &self.spin {
+            Spin::Camera if camera_manager.get_active_camera().is_none() => Err(TurntableError::NoActiveCamera),
+            Spin::Node(path) if node_at(root, path).is_none() => Err(TurntableError::NoSuchNode(path.clone())),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn node_at<'a>(root: &'a mut Node, path: &[usize]) -> Option<&'a mut Node> {
+    path.iter().try_fold(root, |node, &index| node.get_children_mut().get_mut(index))
+}
+
+/// Parses a node path written as child indices separated by slashes, like
+/// `1/0`. An empty string is the root.
+pub fn parse_node_path(path: &str) -> Option<Vec<usize>> {
+    if path.is_empty() {
+        return Some(Vec::new());
+    }
+    path.split('/').map(|index| index.trim().parse().ok()).collect()
+}
+
+#[derive(Debug)]
+pub enum TurntableError {
+    NoFrames,
+    NoActiveCamera,
+    NoSuchNode(Vec<usize>),
+    Io { path: PathBuf, source: std::io::Error },
+    Image { path: PathBuf, source: ImageError },
+}
+
+impl fmt::Display for TurntableError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TurntableError::NoFrames => write!(f, "a turntable needs at least one frame"),
+            TurntableError::NoActiveCamera => write!(f, "the scene has no active camera"),
+            TurntableError::NoSuchNode(path) => write!(f, "no node at {}", display_path(path)),
+            TurntableError::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            TurntableError::Image { path, source } => write!(f, "{}: {}", path.display(), source),
+        }
+    }
+}
+
+impl std::error::Error for TurntableError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TurntableError::Io { source, .. } => Some(source),
+            TurntableError::Image { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+fn display_path(path: &[usize]) -> String {
+    if path.is_empty() {
+        return "the root".to_string();
+    }
+    path.iter().map(usize::to_string).collect::<Vec<_>>().join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::{camera::Camera, scene::{NodeType, Transform}};
+
+    struct Scene {
+        renderer: Renderer,
+        root: Node,
+        models: ModelManager,
+        textures: TextureManager,
+        materials: MaterialManager,
+        cameras: CameraManager,
+    }
+
+    /// An empty group with one child, tilted so a spin shows, and a camera.
+    fn scene() -> Scene {
+        let mut cameras = CameraManager::new();
+        let camera = cameras.add_camera(Camera::new(Vec3::Z, Vec3::ZERO, Vec3::Y, 1.0, 1.0, 0.1, 10.0, 0.0));
+        cameras.set_active_camera(camera);
+        let node = |rotation| Node::new(Box::new(|_, _, _, _| {}), Transform::new(Vec3::ZERO, rotation, Vec3::ONE), NodeType::Group);
+        let mut root = node(Quat::IDENTITY);
+        root.add_child(node(Quat::from_rotation_x(0.5)));
+        Scene {
+            renderer: Renderer::new(8, 8),
+            root,
+            models: ModelManager::new(),
+            textures: TextureManager::new(),
+            materials: MaterialManager::new(),
+            cameras,
+        }
+    }
+
+    fn render<F>(turntable: &Turntable, scene: &mut Scene, frame: F) -> Result<(), TurntableError>
+        where F: FnMut(u32, &Renderer) -> Result<(), TurntableError> {
+        turntable.render(&mut scene.renderer, &mut scene.root, &scene.models, &scene.textures, &scene.materials, &mut scene.cameras, frame)
+    }
+
+    #[test]
+    fn node_spin_restores_the_rotation() {
+        let mut scene = scene();
+        let mut frames = Vec::new();
+        render(&Turntable::new(3, Spin::Node(vec![0])), &mut scene, |i, _| {
+            frames.push(i);
+            Ok(())
+        }).unwrap();
+        assert_eq!(frames, [0, 1, 2]);
+        assert_eq!(scene.root.get_children()[0].transformation.rotation, Quat::from_rotation_x(0.5));
+    }
+
+    #[test]
+    fn node_spin_restores_the_rotation_when_a_frame_fails() {
+        let mut scene = scene();
+        let mut frames = Vec::new();
+        let result = render(&Turntable::new(3, Spin::Node(vec![0])), &mut scene, |i, _| {
+            frames.push(i);
+            match i {
+                1 => Err(TurntableError::Io { path: PathBuf::from("frame"), source: std::io::ErrorKind::Other.into() }),
+                _ => Ok(()),
+            }
+        });
+        assert!(matches!(result, Err(TurntableError::Io { .. })), "{:?}", result);
+        assert_eq!(frames, [0, 1]);
+        assert_eq!(scene.root.get_children()[0].transformation.rotation, Quat::from_rotation_x(0.5));
+    }
+
+    #[test]
+    fn no_frames() {
+        let mut scene = scene();
+        let result = render(&Turntable::new(0, Spin::Camera), &mut scene, |_, _| Ok(()));
+        assert!(matches!(result, Err(TurntableError::NoFrames)), "{:?}", result);
+    }
+
+    #[test]
+    fn node_paths() {
+        assert_eq!(parse_node_path(""), Some(vec![]));
+        assert_eq!(parse_node_path("1/0"), Some(vec![1, 0]));
+        assert_eq!(parse_node_path("x"), None);
+    }
+
+    #[test]
+    fn export_writes_numbered_frames() {
+        let directory = std::env::temp_dir().join(format!("rs_sloth_renderer_{}_turntable", std::process::id()));
+        let output = TurntableOutput { frame_directory: Some(directory.clone()), gif: None };
+        let mut scene = scene();
+        let result = Turntable::new(2, Spin::Camera).export(&output, &mut scene.renderer, &mut scene.root, &scene.models, &scene.textures, &scene.materials, &mut scene.cameras);
+
+        let mut files: Vec<_> = std::fs::read_dir(&directory).unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        files.sort();
+        std::fs::remove_dir_all(&directory).unwrap();
+        result.unwrap();
+        assert_eq!(files, [Path::new("frame_00000.png").as_os_str(), Path::new("frame_00001.png").as_os_str()]);
+    }
+}
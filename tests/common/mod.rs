@@ -0,0 +1,128 @@
+//! Scene building shared by the integration tests.
+
+// Each test binary uses its own subset of these
+#![allow(dead_code)]
+
+use std::sync::Arc;
+
+use glam::{Quat, Vec3};
+use image::RgbaImage;
+use rs_sloth_renderer::{
+    camera::{Camera, CameraManager},
+    light::Light,
+    material::MaterialManager,
+    model::{FaceVertex, Model, ModelId, ModelManager, Submesh},
+    scene::{ModelData, Node, NodeType, Transform},
+    shader::ShadingMode,
+    texture::{Sampler, Texture, TextureId, TextureManager},
+    Renderer,
+};
+
+pub const RED: [u8; 4] = [230, 60, 40, 255];
+pub const WHITE: [u8; 4] = [240, 240, 240, 255];
+
+/// A scene with its own managers, built up by each test.
+pub struct Scene {
+    pub models: ModelManager,
+    pub textures: TextureManager,
+    pub materials: MaterialManager,
+    pub cameras: CameraManager,
+    pub root: Node,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Scene {
+    /// An empty scene with a camera at `position` looking at `target` and
+    /// a white light shining the same way.
+    pub fn new(position: Vec3, target: Vec3, width: usize, height: usize) -> Self {
+        let mut cameras = CameraManager::new();
+        let camera = cameras.add_camera(Camera::new(
+            position,
+            target,
+            Vec3::Y,
+            45f32.to_radians(),
+            width as f32 / height as f32,
+            0.1,
+            100.0,
+            0.0));
+        cameras.set_active_camera(camera);
+
+        let mut root = node(Transform::default(), NodeType::Group);
+        let light_rotation = Quat::from_rotation_arc(Vec3::NEG_Z, (target - position).normalize());
+        root.add_child(node(
+            Transform::new(Vec3::ZERO, light_rotation, Vec3::ONE),
+            NodeType::Light(Arc::new(Light::directional(Vec3::ONE, 1.0)))));
+
+        Self {
+            models: ModelManager::new(),
+            textures: TextureManager::new(),
+            materials: MaterialManager::new(),
+            cameras,
+            root,
+            width,
+            height,
+        }
+    }
+
+    pub fn add_mesh(&mut self, transform: Transform, model_id: ModelId, texture_id: Option<TextureId>, sampler: Sampler, shading: ShadingMode) {
+        self.root.add_child(node(transform, NodeType::Mesh(Arc::new(ModelData {
+            model_id,
+            texture_id,
+            sampler,
+            shading,
+        }))));
+    }
+
+    /// Renders on one thread per core.
+    pub fn render(&self) -> RgbaImage {
+        self.render_with_threads(0)
+    }
+
+    pub fn render_with_threads(&self, threads: usize) -> RgbaImage {
+        let mut renderer = Renderer::new(self.width, self.height);
+        renderer.set_thread_count(threads);
+        renderer.render(&self.root, &self.models, &self.textures, &self.materials, &self.cameras);
+        renderer.to_image()
+    }
+}
+
+pub fn node(transform: Transform, node_type: NodeType) -> Node {
+    Node::new(Box::new(|_node, _input_manager, _camera_manager, _delta_time| {}), transform, node_type)
+}
+
+/// Triangles with their corners' positions and texture coordinates, each
+/// run of `faces` drawn with the matching material.
+pub fn model(verts: &[Vec3], tex_coords: &[Vec3], faces: &[[usize; 3]], submeshes: Vec<Submesh>) -> Model {
+    let corner = |i: usize| FaceVertex {
+        vert: i,
+        tex_coord: (!tex_coords.is_empty()).then_some(i),
+        normal: None,
+    };
+    Model {
+        verts: verts.to_vec(),
+        tex_coords: tex_coords.to_vec(),
+        normals: Vec::new(),
+        faces: faces.iter().map(|face| face.map(corner)).collect(),
+        material_libraries: Vec::new(),
+        submeshes,
+    }
+}
+
+/// A 2x2 unit quad in the XY plane facing +Z, textured from corner to
+/// corner.
+pub fn quad() -> Model {
+    model(
+        &[Vec3::new(-1.0, -1.0, 0.0), Vec3::new(1.0, -1.0, 0.0), Vec3::new(1.0, 1.0, 0.0), Vec3::new(-1.0, 1.0, 0.0)],
+        &[Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0), Vec3::new(1.0, 1.0, 0.0), Vec3::new(0.0, 1.0, 0.0)],
+        &[[0, 1, 2], [0, 2, 3]],
+        vec![Submesh { faces: 0..2, material_name: None, material: None }])
+}
+
+/// `size` by `size` squares of red and white, one texel each.
+pub fn checkerboard(size: usize) -> Texture {
+    let pixels = (0..size * size)
+        .flat_map(|i| if (i % size + i / size) % 2 == 0 { RED } else { WHITE })
+        .collect();
+    Texture::new(size, size, pixels)
+}
@@ -0,0 +1,95 @@
+//! Importing materials, textures and samplers from glTF files.
+
+mod common;
+
+use std::path::PathBuf;
+
+use glam::Vec3;
+use image::{ImageFormat, RgbaImage};
+use rs_sloth_renderer::{
+    gltf_loader::GltfScene,
+    texture::{Filter, MipFilter, Sampler, Wrap},
+};
+
+use common::{Scene, RED, WHITE};
+
+/// A textured triangle with two sets of texture coordinates, the base color
+/// map reading the second. Each test writes its own copy under `name`.
+fn write_gltf(name: &str) -> PathBuf {
+    let directory = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("gltf").join(name);
+    std::fs::create_dir_all(&directory).unwrap();
+
+    let buffer: Vec<u8> = [0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0]
+        .into_iter()
+        .chain([0.0, 0.0, 0.0, 0.0, 0.0, 0.0])
+        .chain([0.0, 0.25, 1.0, 0.25, 0.0, 1.0])
+        .flat_map(|component| component.to_le_bytes())
+        .collect();
+    std::fs::write(directory.join("textured.bin"), &buffer).unwrap();
+
+    let image = RgbaImage::from_fn(2, 2, |x, y| image::Rgba(if (x + y) % 2 == 0 { RED } else { WHITE }));
+    image.save_with_format(directory.join("checker.png"), ImageFormat::Png).unwrap();
+
+    let gltf = r#"{
+        "asset": {"version": "2.0"},
+        "buffers": [{"uri": "textured.bin", "byteLength": 84}],
+        "bufferViews": [{"buffer": 0, "byteLength": 36},
+                        {"buffer": 0, "byteOffset": 36, "byteLength": 24},
+                        {"buffer": 0, "byteOffset": 60, "byteLength": 24}],
+        "accessors": [{"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                       "min": [0, 0, 0], "max": [1, 1, 0]},
+                      {"bufferView": 1, "componentType": 5126, "count": 3, "type": "VEC2"},
+                      {"bufferView": 2, "componentType": 5126, "count": 3, "type": "VEC2"}],
+        "images": [{"uri": "checker.png"}],
+        "samplers": [{"magFilter": 9728, "minFilter": 9984, "wrapS": 33071, "wrapT": 33648}],
+        "textures": [{"source": 0, "sampler": 0}],
+        "materials": [{"name": "checker",
+                       "pbrMetallicRoughness": {"baseColorFactor": [0.5, 0.25, 1.0, 0.75],
+                                                "baseColorTexture": {"index": 0, "texCoord": 1},
+                                                "roughnessFactor": 1.0}}],
+        "meshes": [{"primitives": [{"attributes": {"POSITION": 0, "TEXCOORD_0": 1, "TEXCOORD_1": 2},
+                                    "material": 0}]}],
+        "nodes": [{"mesh": 0}],
+        "scenes": [{"nodes": [0]}],
+        "scene": 0
+    }"#;
+    let path = directory.join("textured.gltf");
+    std::fs::write(&path, gltf).unwrap();
+    path
+}
+
+#[test]
+fn textured_material() {
+    let path = write_gltf("material");
+    let mut scene = Scene::new(Vec3::Z, Vec3::ZERO, 16, 16);
+    let gltf_scene = GltfScene::load(&path, &mut scene.models, &mut scene.textures, &mut scene.materials).unwrap();
+
+    assert_eq!(gltf_scene.textures.len(), 1);
+    let texture = scene.textures.get_texture(gltf_scene.textures[0]);
+    assert_eq!((texture.width, texture.height), (2, 2));
+    assert_eq!(texture.pixels, [RED, WHITE, WHITE, RED].concat());
+
+    assert_eq!(gltf_scene.materials.len(), 1);
+    let material = scene.materials.get_material(gltf_scene.materials[0]);
+    assert_eq!(material.name, "checker");
+    assert_eq!(material.diffuse, Vec3::new(0.5, 0.25, 1.0));
+    assert_eq!(material.opacity, 0.75);
+    assert_eq!(material.diffuse_map, Some(gltf_scene.textures[0]));
+    assert_eq!(material.sampler, Sampler {
+        filter: Filter::Nearest,
+        mip_filter: MipFilter::Nearest,
+        wrap_u: Wrap::Clamp,
+        wrap_v: Wrap::MirroredRepeat,
+    });
+}
+
+#[test]
+fn texture_coordinates_come_from_the_base_color_set() {
+    let path = write_gltf("tex_coords");
+    let mut scene = Scene::new(Vec3::Z, Vec3::ZERO, 16, 16);
+    let gltf_scene = GltfScene::load(&path, &mut scene.models, &mut scene.textures, &mut scene.materials).unwrap();
+
+    // TEXCOORD_1, flipped upside down
+    let model = scene.models.get_model(gltf_scene.models[0]);
+    assert_eq!(model.tex_coords, [Vec3::new(0.0, 0.75, 0.0), Vec3::new(1.0, 0.75, 0.0), Vec3::new(0.0, 0.0, 0.0)]);
+}
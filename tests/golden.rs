@@ -0,0 +1,144 @@
+//! Renders reference scenes headlessly and compares them against the
+//! images checked in under `tests/golden/`.
+//!
+//! On a mismatch the rendered image and a diff, with differing pixels in
+//! red over a faded copy of the golden image, are written next to the
+//! test binary and named in the failure. After an intended change to the
+//! output, regenerate the golden images with
+//!
+//! ```text
+//! BLESS=1 cargo test --test golden
+//! ```
+
+mod common;
+
+use std::path::PathBuf;
+
+use glam::{Quat, Vec3};
+use image::{Rgba, RgbaImage};
+use rs_sloth_renderer::{
+    material::Material,
+    model::Submesh,
+    scene::Transform,
+    shader::ShadingMode,
+    texture::{Sampler, Wrap},
+};
+
+use common::{checkerboard, model, Scene};
+
+const WIDTH: usize = 256;
+const HEIGHT: usize = 256;
+/// Largest difference in any channel for two pixels to count as equal,
+/// leaving room for floating point differences between platforms
+const CHANNEL_TOLERANCE: u8 = 2;
+/// Share of the pixels that may differ, which lets edge pixels flip
+const MAX_DIFFERING_PIXELS: f64 = 0.001;
+
+/// Fails the test unless `actual` matches `tests/golden/<name>.png`, or
+/// replaces the golden image when `BLESS` is set.
+fn assert_golden(name: &str, actual: &RgbaImage) {
+    let golden_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden").join(format!("{}.png", name));
+    if std::env::var_os("BLESS").is_some() {
+        std::fs::create_dir_all(golden_path.parent().unwrap()).unwrap();
+        actual.save(&golden_path).unwrap();
+        return;
+    }
+
+    let golden = match image::open(&golden_path) {
+        Ok(golden) => golden.to_rgba8(),
+        Err(e) => panic!("{}: {}, run with BLESS=1 to create it", golden_path.display(), e),
+    };
+    assert_eq!(golden.dimensions(), actual.dimensions(), "{}: size differs from the golden image", name);
+
+    let mut diff = RgbaImage::new(golden.width(), golden.height());
+    let mut differing = 0;
+    for ((expected, actual), diff) in golden.pixels().zip(actual.pixels()).zip(diff.pixels_mut()) {
+        let matches = expected.0.iter().zip(actual.0).all(|(&e, a)| e.abs_diff(a) <= CHANNEL_TOLERANCE);
+        *diff = if matches {
+            let [r, g, b, _] = expected.0;
+            let faded = ((r as u32 + g as u32 + b as u32) / 12) as u8;
+            Rgba([faded, faded, faded, 255])
+        } else {
+            differing += 1;
+            Rgba([255, 0, 0, 255])
+        };
+    }
+
+    let allowed = (MAX_DIFFERING_PIXELS * (golden.width() * golden.height()) as f64) as usize;
+    if differing > allowed {
+        let output = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("golden");
+        std::fs::create_dir_all(&output).unwrap();
+        let actual_path = output.join(format!("{}.png", name));
+        let diff_path = output.join(format!("{}-diff.png", name));
+        actual.save(&actual_path).unwrap();
+        diff.save(&diff_path).unwrap();
+        panic!("{}: {} pixels differ from the golden image, at most {} may\n  rendered: {}\n  diff: {}",
+               name, differing, allowed, actual_path.display(), diff_path.display());
+    }
+}
+
+#[test]
+fn african_head() {
+    let mut scene = Scene::new(Vec3::new(0.0, 0.0, 3.0), Vec3::ZERO, WIDTH, HEIGHT);
+    let manifest = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let model_id = scene.models.load_model_with_materials(manifest.join("objs/african_head.obj"), &mut scene.textures, &mut scene.materials).unwrap();
+    let texture_id = scene.textures.load_texture(manifest.join("objs/african_head_diffuse.tga")).unwrap();
+    scene.add_mesh(
+        Transform::new(Vec3::ZERO, Quat::from_rotation_y(0.4), Vec3::ONE),
+        model_id,
+        Some(texture_id),
+        Sampler::trilinear(Wrap::Clamp),
+        ShadingMode::Phong);
+
+    assert_golden("african_head", &scene.render());
+}
+
+/// A checkerboard seen at an angle, so texture coordinates have to be
+/// interpolated perspective correctly for the squares to line up.
+#[test]
+fn textured_quad() {
+    let mut scene = Scene::new(Vec3::new(0.0, 1.5, 2.5), Vec3::ZERO, WIDTH, HEIGHT);
+
+    let texture_id = scene.textures.add_texture(checkerboard(8));
+    let model_id = scene.models.add_model(common::quad());
+
+    // Nearly flat on the floor, its far edge foreshortened
+    scene.add_mesh(
+        Transform::new(Vec3::ZERO, Quat::from_rotation_x(-1.2), Vec3::ONE),
+        model_id,
+        Some(texture_id),
+        Sampler::default(),
+        ShadingMode::Flat);
+
+    assert_golden("textured_quad", &scene.render());
+}
+
+/// Three triangles that each pierce the next, so no drawing order gets
+/// them right without the depth buffer.
+#[test]
+fn overlapping_triangles() {
+    let mut scene = Scene::new(Vec3::new(0.0, 0.0, 4.0), Vec3::ZERO, WIDTH, HEIGHT);
+
+    let mut triangles = Vec::new();
+    let mut submeshes = Vec::new();
+    for (i, color) in [Vec3::new(1.0, 0.2, 0.2), Vec3::new(0.2, 1.0, 0.2), Vec3::new(0.2, 0.4, 1.0)].into_iter().enumerate() {
+        let mut material = Material::new(&format!("triangle{}", i));
+        material.diffuse = color;
+        submeshes.push(Submesh { faces: i..i + 1, material_name: Some(material.name.clone()), material: Some(scene.materials.add_material(material)) });
+
+        // A long, thin triangle turned a third of the way around, tilted
+        // so its tip sits in front of the next one and its base behind
+        let rotation = Quat::from_rotation_z(i as f32 * std::f32::consts::TAU / 3.0);
+        triangles.extend([
+            Vec3::new(-0.25, -1.0, -0.5),
+            Vec3::new(0.25, -1.0, -0.5),
+            Vec3::new(0.0, 1.2, 0.5),
+        ].map(|corner| rotation * corner));
+    }
+
+    let faces: Vec<[usize; 3]> = (0..3).map(|i| [3 * i, 3 * i + 1, 3 * i + 2]).collect();
+    let model_id = scene.models.add_model(model(&triangles, &[], &faces, submeshes));
+    scene.add_mesh(Transform::default(), model_id, None, Sampler::default(), ShadingMode::Flat);
+
+    assert_golden("overlapping_triangles", &scene.render());
+}
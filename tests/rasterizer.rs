@@ -0,0 +1,87 @@
+//! Checks on the rasterizer's output that don't need a golden image.
+
+mod common;
+
+use std::path::PathBuf;
+
+use glam::{Quat, Vec3};
+use rs_sloth_renderer::{
+    renderer::TILE_SIZE,
+    scene::Transform,
+    shader::ShadingMode,
+    texture::{Sampler, Wrap},
+};
+
+use common::{checkerboard, Scene};
+
+/// The line between two checker columns is straight on the quad, so it
+/// has to stay straight on screen. Interpolating texture coordinates
+/// without the perspective divide bends it where the quad's two triangles
+/// meet, and more so the flatter the quad is seen.
+#[test]
+fn checker_edge_stays_straight_at_a_grazing_angle() {
+    let mut scene = Scene::new(Vec3::new(0.8, 0.4, 2.5), Vec3::new(0.0, 0.0, -1.0), 256, 256);
+    let texture_id = scene.textures.add_texture(checkerboard(2));
+    let model_id = scene.models.add_model(common::quad());
+    // Nearly flat on the floor and stretched far into the distance
+    scene.add_mesh(
+        Transform::new(Vec3::new(0.0, -0.3, -1.0), Quat::from_rotation_x(-1.4), Vec3::new(1.0, 3.0, 1.0)),
+        model_id,
+        Some(texture_id),
+        Sampler::default(),
+        ShadingMode::Flat);
+    let image = scene.render();
+
+    // Where each row switches from one checker column to the other
+    let mut edge = Vec::new();
+    for y in 0..image.height() {
+        let classes: Vec<(u32, bool)> = (0..image.width())
+            .map(|x| (x, image.get_pixel(x, y).0))
+            .filter(|(_, [r, g, b, _])| (*r, *g, *b) != (0, 0, 0))
+            .map(|(x, [r, g, _, _])| (x, r > g.saturating_mul(2)))
+            .collect();
+        let switches: Vec<u32> = classes.windows(2)
+            .filter(|pair| pair[0].1 != pair[1].1)
+            .map(|pair| pair[1].0)
+            .collect();
+        // Rows crossing the line between checker rows switch irregularly
+        if let [x] = switches[..] {
+            edge.push((y as f32, x as f32));
+        }
+    }
+    assert!(edge.len() > 100, "only {} rows show the checker edge", edge.len());
+
+    let (first, last) = (edge[0], edge[edge.len() - 1]);
+    let slope = (last.1 - first.1) / (last.0 - first.0);
+    for &(y, x) in &edge {
+        let expected = first.1 + (y - first.0) * slope;
+        assert!((x - expected).abs() <= 1.0, "row {}: edge at x = {}, the line through both ends passes {}", y, x, expected);
+    }
+}
+
+/// Tiles are shaded in parallel and cut short at the right and bottom
+/// edges, none of which may change a single pixel.
+#[test]
+fn thread_count_does_not_change_the_image() {
+    // Neither side a multiple of the tile size, and close enough for the
+    // head to reach into the partial tiles along both
+    let (width, height) = (139, 203);
+    assert!(width % TILE_SIZE != 0 && height % TILE_SIZE != 0);
+
+    let mut scene = Scene::new(Vec3::new(0.3, 0.2, 1.5), Vec3::ZERO, width, height);
+    let manifest = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let model_id = scene.models.load_model_with_materials(manifest.join("objs/african_head.obj"), &mut scene.textures, &mut scene.materials).unwrap();
+    let texture_id = scene.textures.load_texture(manifest.join("objs/african_head_diffuse.tga")).unwrap();
+    scene.add_mesh(
+        Transform::new(Vec3::ZERO, Quat::from_rotation_y(0.4), Vec3::ONE),
+        model_id,
+        Some(texture_id),
+        Sampler::trilinear(Wrap::Clamp),
+        ShadingMode::Phong);
+
+    let serial = scene.render_with_threads(1);
+    for threads in [2, 5] {
+        let parallel = scene.render_with_threads(threads);
+        assert!(serial.as_raw() == parallel.as_raw(), "{} threads render a different image than one", threads);
+    }
+}
@@ -0,0 +1,122 @@
+//! Saving and loading scenes through scene files.
+
+mod common;
+
+use std::{path::{Path, PathBuf}, sync::Arc};
+
+use glam::{Quat, Vec3};
+use rs_sloth_renderer::{
+    scene::{CameraEntityData, NodeType, Transform},
+    scene_file::{NodeKind, SceneError, SceneFile},
+    shader::ShadingMode,
+    texture::Sampler,
+};
+
+use common::Scene;
+
+/// A glTF file with two meshes sharing one triangle, and its buffer.
+fn write_gltf(directory: &Path) -> PathBuf {
+    let positions: Vec<u8> = [[0.0f32, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]]
+        .iter()
+        .flatten()
+        .flat_map(|component| component.to_le_bytes())
+        .collect();
+    std::fs::write(directory.join("triangles.bin"), &positions).unwrap();
+
+    let gltf = r#"{
+        "asset": {"version": "2.0"},
+        "buffers": [{"uri": "triangles.bin", "byteLength": 36}],
+        "bufferViews": [{"buffer": 0, "byteLength": 36}],
+        "accessors": [{"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                       "min": [0, 0, 0], "max": [1, 1, 0]}],
+        "meshes": [{"primitives": [{"attributes": {"POSITION": 0}}]},
+                   {"primitives": [{"attributes": {"POSITION": 0}}]}],
+        "nodes": [{"mesh": 0}, {"mesh": 1}],
+        "scenes": [{"nodes": [0, 1]}],
+        "scene": 0
+    }"#;
+    let path = directory.join("triangles.gltf");
+    std::fs::write(&path, gltf).unwrap();
+    path
+}
+
+fn directory(name: &str) -> PathBuf {
+    let directory = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("scene_file").join(name);
+    std::fs::create_dir_all(&directory).unwrap();
+    directory
+}
+
+#[test]
+fn gltf_meshes_round_trip() {
+    let directory = directory("gltf");
+    write_gltf(&directory);
+    let scene_path = directory.join("scene.ron");
+    std::fs::write(&scene_path, r#"#![enable(implicit_some)] (root: (children: [(kind: Mesh((model: "triangles.gltf", mesh: 1)))]))"#).unwrap();
+
+    let mut scene = Scene::new(Vec3::Z, Vec3::ZERO, 16, 16);
+    let root = SceneFile::load_scene(&scene_path, &mut scene.models, &mut scene.textures, &mut scene.materials, &mut scene.cameras).unwrap();
+    let NodeType::Mesh(mesh) = &root.get_children()[0].node_type else {
+        panic!("the mesh node loaded as something else");
+    };
+    assert_eq!(scene.models.get_model_source(mesh.model_id).unwrap().mesh, Some(1));
+
+    let saved_path = directory.join("saved.ron");
+    SceneFile::save_scene(&saved_path, &root, &scene.models, &scene.textures, &scene.cameras).unwrap();
+    let NodeKind::Mesh(mesh) = &SceneFile::load(&saved_path).unwrap().root.children[0].kind else {
+        panic!("the mesh node saved as something else");
+    };
+    assert_eq!(mesh.model, Path::new("triangles.gltf"));
+    assert_eq!(mesh.mesh, Some(1));
+}
+
+#[test]
+fn missing_gltf_mesh() {
+    let directory = directory("missing_mesh");
+    write_gltf(&directory);
+    let scene_path = directory.join("scene.ron");
+    std::fs::write(&scene_path, r#"#![enable(implicit_some)] (root: (kind: Mesh((model: "triangles.gltf", mesh: 2))))"#).unwrap();
+
+    let mut scene = Scene::new(Vec3::Z, Vec3::ZERO, 16, 16);
+    let result = SceneFile::load_scene(&scene_path, &mut scene.models, &mut scene.textures, &mut scene.materials, &mut scene.cameras);
+    assert!(matches!(result, Err(SceneError::NoSuchMesh { mesh: 2, count: 2, .. })), "{:?}", result.err());
+}
+
+#[test]
+fn models_not_from_files_fail_to_save() {
+    let mut scene = Scene::new(Vec3::Z, Vec3::ZERO, 16, 16);
+    let model_id = scene.models.add_model(common::quad());
+    scene.add_mesh(Transform::default(), model_id, None, Sampler::default(), ShadingMode::Flat);
+
+    let path = directory("not_from_file").join("scene.ron");
+    let result = SceneFile::save_scene(&path, &scene.root, &scene.models, &scene.textures, &scene.cameras);
+    // The light comes first
+    assert!(matches!(&result, Err(SceneError::ModelNotFromFile(node)) if node == &[1]), "{:?}", result.err());
+    assert!(!path.exists());
+}
+
+#[test]
+fn cameras_under_moved_nodes_fail_to_load() {
+    let scene_path = directory("nested_camera_load").join("scene.ron");
+    std::fs::write(&scene_path, r#"(root: (children: [
+        (kind: Camera((target: (0.0, 0.0, -1.0)))),
+        (transform: (position: (1.0, 0.0, 0.0)), children: [(kind: Camera((target: (0.0, 0.0, -1.0))))]),
+    ]))"#).unwrap();
+
+    let mut scene = Scene::new(Vec3::Z, Vec3::ZERO, 16, 16);
+    let result = SceneFile::load_scene(&scene_path, &mut scene.models, &mut scene.textures, &mut scene.materials, &mut scene.cameras);
+    assert!(matches!(&result, Err(SceneError::NestedCamera(node)) if node == &[1, 0]), "{:?}", result.err());
+}
+
+#[test]
+fn cameras_under_moved_nodes_fail_to_save() {
+    let mut scene = Scene::new(Vec3::Z, Vec3::ZERO, 16, 16);
+    let camera = scene.cameras.active_camera.unwrap();
+    let mut group = common::node(Transform::new(Vec3::X, Quat::IDENTITY, Vec3::ONE), NodeType::Group);
+    group.add_child(common::node(Transform::default(), NodeType::Camera(Arc::new(CameraEntityData { camera, speed: 1.0 }))));
+    scene.root.add_child(group);
+
+    let path = directory("nested_camera_save").join("scene.ron");
+    let result = SceneFile::save_scene(&path, &scene.root, &scene.models, &scene.textures, &scene.cameras);
+    assert!(matches!(&result, Err(SceneError::NestedCamera(node)) if node == &[1, 0]), "{:?}", result.err());
+    assert!(!path.exists());
+}